@@ -3,7 +3,9 @@ use crate::pages::{GameState, OpponentHand, OurHand, Route};
 use dioxus::prelude::*;
 use dioxus_router::prelude::*;
 use indextree::NodeId;
-use poker::{Game, Hand, State, DECK_OF_CARDS};
+use std::time::Duration;
+
+use poker::{Config, Game, Hand, Mode, Outcome, State, DECK_OF_CARDS};
 
 use super::history::HisHand;
 use super::RemainHand;
@@ -23,6 +25,7 @@ pub fn PokerGame(cx: Scope) -> Element {
     let opponent_played_hands = use_state(cx, Vec::<(NodeId, Hand)>::new);
     let init_hand = use_state(cx, || (Hand::default(), Hand::default(), 0_u8));
     let no_solution = use_state(cx, || false);
+    let timed_out = use_state(cx, || false);
 
     // 根据游戏状态,控制手牌选择框的样式,和出牌区域的隐藏状态
     let (our_outline, opponent_outline, playing_hidden) = match *game_state.read() {
@@ -133,6 +136,7 @@ pub fn PokerGame(cx: Scope) -> Element {
                 *inner_game_state = GameState::Playing;
                 nav.replace(Route::History {});
                 let game_pass;
+                let game_timed_out;
 
                 // 校验初始游戏状态是否发生实质性变化（无视花色的手牌和先手比较）
                 let our_suit_hand = our_hand.read().0;
@@ -149,18 +153,32 @@ pub fn PokerGame(cx: Scope) -> Element {
                         }
                     };
 
-                if new_state != State::new(vec![old_our, old_opponent], old_turn).unwrap() {
-                    let new_game = Game::from_state(new_state);
+                // 第一次开始或清空之后没有上一局，旧的手牌为空时也建不出局面
+                let old_state = State::new(vec![old_our, old_opponent], old_turn).ok();
+                let old_game = game
+                    .get()
+                    .as_ref()
+                    .filter(|_| old_state.as_ref() == Some(&new_state));
+                if let Some(old_game) = old_game {
+                    game_pass = old_game.pass();
+                    game_timed_out = old_game.outcome() == Outcome::Unknown;
+                    log::trace!("初始状态没有实质性变化，直接继续");
+                } else {
+                    let config = Config {
+                        mode: Mode::Search,
+                        timeout: Some(Duration::from_secs(30)),
+                        ..Default::default()
+                    };
+                    let new_game = Game::with_config(new_state, config);
                     current_node_id.set(Some(new_game.root));
                     game_pass = new_game.pass();
+                    game_timed_out = new_game.outcome() == Outcome::Unknown;
                     game.set(Some(new_game));
-                } else {
-                    game_pass = game.get().as_ref().unwrap().pass();
-                    log::trace!("初始状态没有实质性变化，直接继续");
                 }
                 init_hand.set((our_suit_hand, opponent_suit_hand, current_turn));
 
                 no_solution.set(!game_pass);
+                timed_out.set(game_timed_out);
                 if game_pass {
                     //出牌
                     play_next();
@@ -285,7 +303,13 @@ pub fn PokerGame(cx: Scope) -> Element {
             div {
                 class: "flex flex-row flex-wrap shadow min-w-full pr-2 pb-2 justify-center rounded-xl bg-red-100 items-center {no_solution_hidden}",
                 style: "font-family: 楷体",
-                h1 { class: "text-9xl text-center", "无解" }
+                h1 { class: "text-9xl text-center",
+                    if *timed_out.get() {
+                        "超时"
+                    } else {
+                        "无解"
+                    }
+                }
             }
 
             div { class: "flex flex-col space-y-6 {playing_hidden}",
@@ -1,11 +1,12 @@
 use std::fmt::{Display, Formatter};
 
 use crate::card::Card;
+use crate::combination::Combination;
 use crate::game::{Carry, StraightType};
 
 /// 牌组
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub(crate) enum Action {
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
     /// 不要
     None,
     /// 单张
@@ -78,11 +79,64 @@ pub(crate) enum Action {
     QuadSingle(Card, Card, Card),
     /// 四带二对
     QuadPair(Card, Card, Card),
+    /// 火箭带二单，按四带二算
+    RocketSingle(Card, Card),
+    /// 火箭带二对
+    RocketPair(Card, Card),
 
     /// 炸弹
     Bomb(Card),
+    /// 用癞子凑成的软炸弹，比同张数的硬炸弹小
+    SoftBomb(Card),
+    /// 五张的炸弹，两副牌时才有
+    Bomb5(Card),
+    /// 五张的软炸弹
+    SoftBomb5(Card),
+    /// 六张的炸弹
+    Bomb6(Card),
+    /// 六张的软炸弹
+    SoftBomb6(Card),
+    /// 七张的炸弹
+    Bomb7(Card),
+    /// 七张的软炸弹
+    SoftBomb7(Card),
+    /// 八张的炸弹
+    Bomb8(Card),
+    /// 八张的软炸弹
+    SoftBomb8(Card),
     /// 火箭
     Rocket,
+    /// 天王炸，两副牌的四张王
+    KingBomb,
+}
+
+impl Action {
+    /// 炸弹的大小，不是炸弹时为None。先比张数，张数相同时硬炸弹比软炸弹大，再比点数；
+    /// 火箭、天王炸依次最大。第一项除以2是张数
+    pub(crate) fn bomb_order(&self) -> Option<(u8, Card)> {
+        let (size, hard, c) = match *self {
+            Action::SoftBomb(c) => (4, 0, c),
+            Action::Bomb(c) => (4, 1, c),
+            Action::SoftBomb5(c) => (5, 0, c),
+            Action::Bomb5(c) => (5, 1, c),
+            Action::SoftBomb6(c) => (6, 0, c),
+            Action::Bomb6(c) => (6, 1, c),
+            Action::SoftBomb7(c) => (7, 0, c),
+            Action::Bomb7(c) => (7, 1, c),
+            Action::SoftBomb8(c) => (8, 0, c),
+            Action::Bomb8(c) => (8, 1, c),
+            Action::Rocket => (9, 0, Card::BlackJoker),
+            Action::KingBomb => (10, 0, Card::BlackJoker),
+            _ => return None,
+        };
+        Some((2 * size + hard, c))
+    }
+
+    /// 两手牌只有带的牌不同。带的牌不影响大小，出完剩下的手牌也相同时两手牌等价
+    pub(crate) fn same_but_kickers(&self, other: &Action) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && Combination(*self).rank() == Combination(*other).rank()
+    }
 }
 
 impl From<Action> for Vec<Card> {
@@ -118,7 +172,7 @@ impl From<Action> for Vec<Card> {
                 Carry::None,
                 Vec::new(),
             ),
-            Action::Pair(c) => straight_cards(StraightType::Pair, c, 1, Carry::None, Vec::new()),
+            Action::Pair(c) => vec![c, c],
             Action::PairSequence3(c) => {
                 straight_cards(StraightType::Pair, c, 3, Carry::None, Vec::new())
             }
@@ -202,8 +256,22 @@ impl From<Action> for Vec<Card> {
             }
             Action::QuadSingle(c1, c2, c3) => vec![c1, c1, c1, c1, c2, c3],
             Action::QuadPair(c1, c2, c3) => vec![c1, c1, c1, c1, c2, c2, c3, c3],
-            Action::Bomb(c) => vec![c, c, c, c],
+            Action::RocketSingle(c1, c2) => vec![Card::BlackJoker, Card::RedJoker, c1, c2],
+            Action::RocketPair(c1, c2) => {
+                vec![Card::BlackJoker, Card::RedJoker, c1, c1, c2, c2]
+            }
+            Action::Bomb(c) | Action::SoftBomb(c) => vec![c, c, c, c],
+            Action::Bomb5(c) | Action::SoftBomb5(c) => vec![c; 5],
+            Action::Bomb6(c) | Action::SoftBomb6(c) => vec![c; 6],
+            Action::Bomb7(c) | Action::SoftBomb7(c) => vec![c; 7],
+            Action::Bomb8(c) | Action::SoftBomb8(c) => vec![c; 8],
             Action::Rocket => vec![Card::BlackJoker, Card::RedJoker],
+            Action::KingBomb => vec![
+                Card::BlackJoker,
+                Card::BlackJoker,
+                Card::RedJoker,
+                Card::RedJoker,
+            ],
         }
     }
 }
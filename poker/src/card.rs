@@ -1,5 +1,6 @@
 use crate::Error;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum SuitCard {
@@ -23,6 +24,31 @@ impl SuitCard {
             _ => unreachable!(),
         }
     }
+
+    pub fn card(&self) -> Card {
+        match *self {
+            SuitCard::Spades(card)
+            | SuitCard::Hearts(card)
+            | SuitCard::Clubs(card)
+            | SuitCard::Diamonds(card) => card,
+        }
+    }
+
+    /// 花色的符号，也接受字母S、H、C，方块的字母是F（D已经是大王）
+    pub(crate) fn suit_from_char(c: char) -> Option<u8> {
+        match c {
+            '♠' | 's' | 'S' => Some(3),
+            '♥' | 'h' | 'H' => Some(2),
+            '♣' | 'c' | 'C' => Some(1),
+            '♦' | 'f' | 'F' => Some(0),
+            _ => None,
+        }
+    }
+
+    /// 是否是带花色的写法。花色字母都不是点数，出现任何一个就是
+    pub(crate) fn is_suit_notation(s: &str) -> bool {
+        s.chars().any(|c| SuitCard::suit_from_char(c).is_some())
+    }
 }
 
 impl From<SuitCard> for u64 {
@@ -39,7 +65,7 @@ impl From<SuitCard> for u64 {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Card {
     Three = 1,
     Four = 1 << 1,
@@ -58,7 +84,66 @@ pub enum Card {
     RedJoker = 1 << 14,
 }
 
+impl Display for SuitCard {
+    /// 规范写法是花色符号加点数，如♠A；大小王没有花色，写作X、D
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let suit = match self {
+            _ if self.card() >= Card::BlackJoker => "",
+            SuitCard::Spades(_) => "♠",
+            SuitCard::Hearts(_) => "♥",
+            SuitCard::Clubs(_) => "♣",
+            SuitCard::Diamonds(_) => "♦",
+        };
+        write!(f, "{}{}", suit, self.card())
+    }
+}
+
+impl FromStr for SuitCard {
+    type Err = Error;
+
+    /// 解析一张带花色的牌，如"♠A"或"SA"；大小王只写点数
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidCardValue(s.to_string());
+        let mut chars = s.chars();
+        let (first, second) = (chars.next().ok_or_else(invalid)?, chars.next());
+        if chars.next().is_some() {
+            return Err(invalid());
+        }
+        match second {
+            None => {
+                let card = Card::from_char(first)?;
+                if card < Card::BlackJoker {
+                    return Err(invalid());
+                }
+                Ok(SuitCard::Diamonds(card))
+            }
+            Some(rank) => {
+                let suit = SuitCard::suit_from_char(first).ok_or_else(invalid)?;
+                let card = Card::from_char(rank)?;
+                if card >= Card::BlackJoker {
+                    return Err(Error::SuitedJoker(card, 0));
+                }
+                Ok(SuitCard::new(card, suit))
+            }
+        }
+    }
+}
+
+impl FromStr for Card {
+    type Err = Error;
+
+    /// 解析一个点数字符
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Card::from_char(c),
+            _ => Err(Error::InvalidCardValue(s.to_string())),
+        }
+    }
+}
+
 impl Card {
+    /// 规范写法是Display输出的3456789TJQKA2XD，输入时也接受小写字母和别名：1是A，B是小王，R是大王
     pub(crate) fn from_char(c: char) -> Result<Card, Error> {
         match c {
             '3' => Ok(Card::Three),
@@ -74,8 +159,8 @@ impl Card {
             'k' | 'K' => Ok(Card::King),
             '1' | 'a' | 'A' => Ok(Card::Ace),
             '2' => Ok(Card::Two),
-            'x' | 'X' => Ok(Card::BlackJoker),
-            'd' | 'D' => Ok(Card::RedJoker),
+            'x' | 'X' | 'b' | 'B' => Ok(Card::BlackJoker),
+            'd' | 'D' | 'r' | 'R' => Ok(Card::RedJoker),
             _ => Err(Error::InvalidCardValue(c.to_string())),
         }
     }
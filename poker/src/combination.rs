@@ -0,0 +1,581 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::action::Action;
+use crate::card::Card;
+use crate::hand::Cards;
+use crate::{BeatError, Error};
+
+/// 牌型的类别
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Category {
+    /// 单张
+    Single,
+    /// 顺子
+    Sequence,
+    /// 对子
+    Pair,
+    /// 连对
+    PairSequence,
+    /// 三张
+    Triplet,
+    /// 三带一单
+    TripletSingle,
+    /// 三带一对
+    TripletPair,
+    /// 飞机
+    TripletSequence,
+    /// 飞机带单
+    TripletSequenceSingle,
+    /// 飞机带对
+    TripletSequencePair,
+    /// 四带二单
+    QuadSingle,
+    /// 四带二对
+    QuadPair,
+    /// 火箭带二单
+    RocketSingle,
+    /// 火箭带二对
+    RocketPair,
+    /// 炸弹，两副牌时有四到八张
+    Bomb,
+    /// 用癞子凑成的软炸弹
+    SoftBomb,
+    /// 火箭
+    Rocket,
+    /// 天王炸
+    KingBomb,
+}
+
+/// 一手合法的牌，不包括“不要”
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Combination(pub(crate) Action);
+
+impl Combination {
+    pub fn category(&self) -> Category {
+        self.parts().0
+    }
+
+    /// 主体部分最小的点数：顺子、连对、飞机是起始的点数，带牌的是三张或四张的点数，
+    /// 火箭和火箭带牌是小王
+    pub fn rank(&self) -> Card {
+        self.parts().1
+    }
+
+    /// 主体部分连续的点数个数：顺子的张数、连对的对数、飞机的三张数，其它牌型是1
+    pub fn length(&self) -> u8 {
+        self.parts().2
+    }
+
+    /// 带的牌，每对只列一张，从小到大
+    pub fn kickers(&self) -> Vec<Card> {
+        self.parts().3
+    }
+
+    /// 所有的牌
+    pub fn cards(&self) -> Vec<Card> {
+        self.0.into()
+    }
+
+    /// 按H的副数识别牌型：一副牌时与TryFrom相同，两副牌时还有五到八张的炸弹和天王炸，没有火箭
+    pub fn classify<H: Cards>(cards: &[Card]) -> Result<Self, Error> {
+        classify(cards, H::DECKS)
+    }
+
+    /// 按H的副数从牌面字符识别牌型，如"33333"
+    pub fn classify_str<H: Cards>(s: &str) -> Result<Self, Error> {
+        Self::classify::<H>(&parse_cards(s)?)
+    }
+
+    /// 能否管上other：天王炸、火箭依次最大；炸弹管非炸弹，炸弹之间先比张数再比点数，
+    /// 张数相同时硬炸弹管软炸弹，同是软炸弹时比点数；
+    /// 火箭带牌管同样带法的四带二；其余牌型要类别和长度都相同，主体点数更大，带的牌不比较
+    pub fn beats(&self, other: &Combination) -> Result<(), BeatError> {
+        use Category::*;
+        let (mine, theirs) = (self.0.bomb_order(), other.0.bomb_order());
+        match (self.category(), other.category()) {
+            (_, KingBomb) => Err(BeatError::KingBombPrecedence),
+            (_, Rocket) if mine < theirs => Err(BeatError::RocketPrecedence),
+            _ if mine > theirs => Ok(()),
+            // 张数相同时硬炸弹大
+            (SoftBomb, Bomb) if mine.map(|m| m.0 / 2) == theirs.map(|t| t.0 / 2) => {
+                Err(BeatError::HardBombPrecedence)
+            }
+            _ if theirs.is_some() && mine.map(|m| m.0) == theirs.map(|t| t.0) => {
+                Err(BeatError::RankTooLow)
+            }
+            _ if theirs.is_some() => Err(BeatError::BombPrecedence),
+            (RocketSingle, QuadSingle) | (RocketPair, QuadPair) => Ok(()),
+            (QuadSingle, RocketSingle) | (QuadPair, RocketPair) => Err(BeatError::RankTooLow),
+            (a, b) if a != b => Err(BeatError::CategoryMismatch),
+            _ if self.length() != other.length() => Err(BeatError::LengthMismatch),
+            _ if self.rank() <= other.rank() => Err(BeatError::RankTooLow),
+            _ => Ok(()),
+        }
+    }
+
+    /// 编码用的各部分：与parts相同，只是炸弹的长度是张数，用来区分四到八张的炸弹
+    pub(crate) fn encode(&self) -> (Category, Card, u8, Vec<Card>) {
+        let (category, c, length, kickers) = self.parts();
+        let length = match category {
+            Category::Bomb | Category::SoftBomb => self.cards().len() as u8,
+            _ => length,
+        };
+        (category, c, length, kickers)
+    }
+
+    fn parts(&self) -> (Category, Card, u8, Vec<Card>) {
+        use Category::*;
+        match self.0 {
+            Action::None => unreachable!(),
+            Action::Single(c) => (Single, c, 1, Vec::new()),
+            Action::Sequence5(c) => (Sequence, c, 5, Vec::new()),
+            Action::Sequence6(c) => (Sequence, c, 6, Vec::new()),
+            Action::Sequence7(c) => (Sequence, c, 7, Vec::new()),
+            Action::Sequence8(c) => (Sequence, c, 8, Vec::new()),
+            Action::Sequence9(c) => (Sequence, c, 9, Vec::new()),
+            Action::Sequence10(c) => (Sequence, c, 10, Vec::new()),
+            Action::Sequence11(c) => (Sequence, c, 11, Vec::new()),
+            Action::Sequence12 => (Sequence, Card::Three, 12, Vec::new()),
+            Action::Pair(c) => (Pair, c, 1, Vec::new()),
+            Action::PairSequence3(c) => (PairSequence, c, 3, Vec::new()),
+            Action::PairSequence4(c) => (PairSequence, c, 4, Vec::new()),
+            Action::PairSequence5(c) => (PairSequence, c, 5, Vec::new()),
+            Action::PairSequence6(c) => (PairSequence, c, 6, Vec::new()),
+            Action::PairSequence7(c) => (PairSequence, c, 7, Vec::new()),
+            Action::PairSequence8(c) => (PairSequence, c, 8, Vec::new()),
+            Action::PairSequence9(c) => (PairSequence, c, 9, Vec::new()),
+            Action::PairSequence10(c) => (PairSequence, c, 10, Vec::new()),
+            Action::Triplet(c) => (Triplet, c, 1, Vec::new()),
+            Action::TripletSingle(c, k) => (TripletSingle, c, 1, vec![k]),
+            Action::TripletPair(c, k) => (TripletPair, c, 1, vec![k]),
+            Action::TripletSequence2(c) => (TripletSequence, c, 2, Vec::new()),
+            Action::TripletSequence2Single(c, k1, k2) => {
+                (TripletSequenceSingle, c, 2, vec![k1, k2])
+            }
+            Action::TripletSequence2Pair(c, k1, k2) => (TripletSequencePair, c, 2, vec![k1, k2]),
+            Action::TripletSequence3(c) => (TripletSequence, c, 3, Vec::new()),
+            Action::TripletSequence3Single(c, k1, k2, k3) => {
+                (TripletSequenceSingle, c, 3, vec![k1, k2, k3])
+            }
+            Action::TripletSequence3Pair(c, k1, k2, k3) => {
+                (TripletSequencePair, c, 3, vec![k1, k2, k3])
+            }
+            Action::TripletSequence4(c) => (TripletSequence, c, 4, Vec::new()),
+            Action::TripletSequence4Single(c, k1, k2, k3, k4) => {
+                (TripletSequenceSingle, c, 4, vec![k1, k2, k3, k4])
+            }
+            Action::TripletSequence4Pair(c, k1, k2, k3, k4) => {
+                (TripletSequencePair, c, 4, vec![k1, k2, k3, k4])
+            }
+            Action::TripletSequence5(c) => (TripletSequence, c, 5, Vec::new()),
+            Action::TripletSequence5Single(c, k1, k2, k3, k4, k5) => {
+                (TripletSequenceSingle, c, 5, vec![k1, k2, k3, k4, k5])
+            }
+            Action::TripletSequence6(c) => (TripletSequence, c, 6, Vec::new()),
+            Action::QuadSingle(c, k1, k2) => (QuadSingle, c, 1, vec![k1, k2]),
+            Action::QuadPair(c, k1, k2) => (QuadPair, c, 1, vec![k1, k2]),
+            Action::RocketSingle(k1, k2) => (RocketSingle, Card::BlackJoker, 1, vec![k1, k2]),
+            Action::RocketPair(k1, k2) => (RocketPair, Card::BlackJoker, 1, vec![k1, k2]),
+            Action::Bomb(c) => (Bomb, c, 1, Vec::new()),
+            Action::SoftBomb(c)
+            | Action::SoftBomb5(c)
+            | Action::SoftBomb6(c)
+            | Action::SoftBomb7(c)
+            | Action::SoftBomb8(c) => (SoftBomb, c, 1, Vec::new()),
+            Action::Bomb5(c) | Action::Bomb6(c) | Action::Bomb7(c) | Action::Bomb8(c) => {
+                (Bomb, c, 1, Vec::new())
+            }
+            Action::Rocket => (Rocket, Card::BlackJoker, 1, Vec::new()),
+            Action::KingBomb => (KingBomb, Card::BlackJoker, 1, Vec::new()),
+        }
+    }
+}
+
+/// 由牌型的各部分组成Action，没有对应的牌型时返回None
+pub(crate) fn action(category: Category, c: Card, length: u8, k: &[Card]) -> Option<Action> {
+    use Category::*;
+    let action = match (category, length, k.len()) {
+        (Single, 1, 0) => Action::Single(c),
+        (Sequence, 5, 0) => Action::Sequence5(c),
+        (Sequence, 6, 0) => Action::Sequence6(c),
+        (Sequence, 7, 0) => Action::Sequence7(c),
+        (Sequence, 8, 0) => Action::Sequence8(c),
+        (Sequence, 9, 0) => Action::Sequence9(c),
+        (Sequence, 10, 0) => Action::Sequence10(c),
+        (Sequence, 11, 0) => Action::Sequence11(c),
+        (Sequence, 12, 0) => Action::Sequence12,
+        (Pair, 1, 0) => Action::Pair(c),
+        (PairSequence, 3, 0) => Action::PairSequence3(c),
+        (PairSequence, 4, 0) => Action::PairSequence4(c),
+        (PairSequence, 5, 0) => Action::PairSequence5(c),
+        (PairSequence, 6, 0) => Action::PairSequence6(c),
+        (PairSequence, 7, 0) => Action::PairSequence7(c),
+        (PairSequence, 8, 0) => Action::PairSequence8(c),
+        (PairSequence, 9, 0) => Action::PairSequence9(c),
+        (PairSequence, 10, 0) => Action::PairSequence10(c),
+        (Triplet, 1, 0) => Action::Triplet(c),
+        (TripletSingle, 1, 1) => Action::TripletSingle(c, k[0]),
+        (TripletPair, 1, 1) => Action::TripletPair(c, k[0]),
+        (TripletSequence, 2, 0) => Action::TripletSequence2(c),
+        (TripletSequence, 3, 0) => Action::TripletSequence3(c),
+        (TripletSequence, 4, 0) => Action::TripletSequence4(c),
+        (TripletSequence, 5, 0) => Action::TripletSequence5(c),
+        (TripletSequence, 6, 0) => Action::TripletSequence6(c),
+        (TripletSequenceSingle, 2, 2) => Action::TripletSequence2Single(c, k[0], k[1]),
+        (TripletSequenceSingle, 3, 3) => Action::TripletSequence3Single(c, k[0], k[1], k[2]),
+        (TripletSequenceSingle, 4, 4) => Action::TripletSequence4Single(c, k[0], k[1], k[2], k[3]),
+        (TripletSequenceSingle, 5, 5) => {
+            Action::TripletSequence5Single(c, k[0], k[1], k[2], k[3], k[4])
+        }
+        (TripletSequencePair, 2, 2) => Action::TripletSequence2Pair(c, k[0], k[1]),
+        (TripletSequencePair, 3, 3) => Action::TripletSequence3Pair(c, k[0], k[1], k[2]),
+        (TripletSequencePair, 4, 4) => Action::TripletSequence4Pair(c, k[0], k[1], k[2], k[3]),
+        (QuadSingle, 1, 2) => Action::QuadSingle(c, k[0], k[1]),
+        (QuadPair, 1, 2) => Action::QuadPair(c, k[0], k[1]),
+        (RocketSingle, 1, 2) => Action::RocketSingle(k[0], k[1]),
+        (RocketPair, 1, 2) => Action::RocketPair(k[0], k[1]),
+        (Bomb, 1, 0) => Action::Bomb(c),
+        (Rocket, 1, 0) => Action::Rocket,
+        _ => return None,
+    };
+    Some(action)
+}
+
+/// 由encode的各部分还原Action，没有对应的牌型时返回None
+pub(crate) fn decode(category: Category, c: Card, length: u8, k: &[Card]) -> Option<Action> {
+    use Category::*;
+    // 连续的牌型最大到A
+    let straight = matches!(
+        category,
+        Sequence | PairSequence | TripletSequence | TripletSequenceSingle | TripletSequencePair
+    );
+    if straight && index(c) + length as usize > index(Card::Ace) + 1 {
+        return None;
+    }
+    match (category, length, k.len()) {
+        (Bomb, 4, 0) => Some(Action::Bomb(c)),
+        (Bomb, 5, 0) => Some(Action::Bomb5(c)),
+        (Bomb, 6, 0) => Some(Action::Bomb6(c)),
+        (Bomb, 7, 0) => Some(Action::Bomb7(c)),
+        (Bomb, 8, 0) => Some(Action::Bomb8(c)),
+        (SoftBomb, 4, 0) => Some(Action::SoftBomb(c)),
+        (SoftBomb, 5, 0) => Some(Action::SoftBomb5(c)),
+        (SoftBomb, 6, 0) => Some(Action::SoftBomb6(c)),
+        (SoftBomb, 7, 0) => Some(Action::SoftBomb7(c)),
+        (SoftBomb, 8, 0) => Some(Action::SoftBomb8(c)),
+        (Bomb | SoftBomb, ..) => None,
+        (KingBomb, 1, 0) => Some(Action::KingBomb),
+        _ => action(category, c, length, k),
+    }
+}
+
+/// 点数的序号，3是0，大王是14
+fn index(card: Card) -> usize {
+    (card as u16).trailing_zeros() as usize
+}
+
+fn card(index: usize) -> Card {
+    Card::from_u16(1 << index).unwrap()
+}
+
+/// 从高到低找连续length个点数张数都不少于count的区间，返回起始的序号。连续的最大到A
+fn runs(counts: &[u8; 15], count: u8, length: usize) -> impl Iterator<Item = usize> + '_ {
+    let last = if length == 1 { Card::Two } else { Card::Ace };
+    (0..=(index(last) + 1).saturating_sub(length))
+        .rev()
+        .filter(move |&start| counts[start..start + length].iter().all(|&n| n >= count))
+}
+
+/// 按点数列出剩下的牌，count张算一个，张数不是count的倍数时返回None
+fn group(counts: &[u8; 15], count: u8) -> Option<Vec<Card>> {
+    let mut cards = Vec::new();
+    for (i, &n) in counts.iter().enumerate() {
+        if n % count != 0 {
+            return None;
+        }
+        for _ in 0..n / count {
+            cards.push(card(i));
+        }
+    }
+    Some(cards)
+}
+
+fn parse_cards(s: &str) -> Result<Vec<Card>, Error> {
+    s.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(Card::from_char)
+        .collect()
+}
+
+/// 识别一手牌的牌型，与顺序无关。有多种理解时，依次优先火箭、炸弹、不带牌的牌型、
+/// 三带、飞机带翅膀、四带二、火箭带牌，同类中优先点数大的。
+/// 不考虑RuleSet；某个点数的张数超出decks副牌的上限时不是合法的牌型
+fn classify(cards: &[Card], decks: u8) -> Result<Combination, Error> {
+    use Category::*;
+    let invalid =
+        || Error::InvalidCombination(cards.iter().map(|c| c.to_string()).collect::<String>());
+    let n = cards.len();
+    let mut counts = [0_u8; 15];
+    for &c in cards {
+        counts[index(c)] += 1;
+    }
+    let (black, red) = (
+        counts[index(Card::BlackJoker)],
+        counts[index(Card::RedJoker)],
+    );
+    if counts.iter().any(|&c| c > 4 * decks) || black > decks || red > decks {
+        return Err(invalid());
+    }
+    let ranks = counts.iter().filter(|&&c| c > 0).count();
+    let first = counts.iter().position(|&c| c > 0).ok_or_else(invalid)?;
+    let c = card(first);
+
+    let mut found = match (n, ranks) {
+        // 两副牌时没有火箭
+        (2, 2) if decks < 2 && black == 1 && red == 1 => action(Rocket, c, 1, &[]),
+        (1, 1) => action(Single, c, 1, &[]),
+        (2, 1) => action(Pair, c, 1, &[]),
+        (3, 1) => action(Triplet, c, 1, &[]),
+        (4, 1) => action(Bomb, c, 1, &[]),
+        (4, 2) if black == 2 && red == 2 => Some(Action::KingBomb),
+        (5, 1) if c <= Card::Two => Some(Action::Bomb5(c)),
+        (6, 1) if c <= Card::Two => Some(Action::Bomb6(c)),
+        (7, 1) if c <= Card::Two => Some(Action::Bomb7(c)),
+        (8, 1) if c <= Card::Two => Some(Action::Bomb8(c)),
+        _ => None,
+    };
+
+    // 顺子、连对、飞机
+    for (category, count) in [(Sequence, 1), (PairSequence, 2), (TripletSequence, 3)] {
+        let length = n / count as usize;
+        if found.is_none() && ranks == length && n.is_multiple_of(count as usize) {
+            if let Some(start) = runs(&counts, count, length).next() {
+                found = action(category, card(start), length as u8, &[]);
+            }
+        }
+    }
+
+    // 三带、飞机带翅膀、四带二：主体之外的牌都是带的牌
+    let carries = [
+        (TripletSingle, 3, 1, 1),
+        (TripletPair, 3, 2, 1),
+        (TripletSequenceSingle, 3, 1, 0),
+        (TripletSequencePair, 3, 2, 0),
+        (QuadSingle, 4, 1, 1),
+        (QuadPair, 4, 2, 1),
+    ];
+    for (category, count, carry, fixed) in carries {
+        if found.is_some() {
+            break;
+        }
+        // 每组主体带的张数，fixed为1表示主体只有一组，否则是飞机
+        let per = count as usize + carry as usize * if count == 4 { 2 } else { 1 };
+        if !n.is_multiple_of(per) {
+            continue;
+        }
+        let length = n / per;
+        if (fixed == 1) != (length == 1) {
+            continue;
+        }
+        for start in runs(&counts, count, length) {
+            let mut rest = counts;
+            for r in &mut rest[start..start + length] {
+                *r -= count;
+            }
+            let Some(kickers) = group(&rest, carry) else {
+                continue;
+            };
+            if let Some(a) = action(category, card(start), length as u8, &kickers) {
+                found = Some(a);
+                break;
+            }
+        }
+    }
+
+    // 火箭带二单或二对，两副牌时没有火箭
+    if found.is_none() && decks < 2 && black == 1 && red == 1 {
+        let mut rest = counts;
+        rest[index(Card::BlackJoker)] = 0;
+        rest[index(Card::RedJoker)] = 0;
+        found = match n {
+            4 => group(&rest, 1).and_then(|k| action(RocketSingle, Card::BlackJoker, 1, &k)),
+            6 => group(&rest, 2).and_then(|k| action(RocketPair, Card::BlackJoker, 1, &k)),
+            _ => None,
+        };
+    }
+
+    found.map(Combination).ok_or_else(invalid)
+}
+
+impl TryFrom<&[Card]> for Combination {
+    type Error = Error;
+
+    /// 按一副牌识别一手牌的牌型，两副牌用Combination::classify
+    fn try_from(cards: &[Card]) -> Result<Self, Self::Error> {
+        classify(cards, 1)
+    }
+}
+
+impl FromStr for Combination {
+    type Err = Error;
+
+    /// 按一副牌从牌面字符识别牌型，如"334455"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Combination::try_from(parse_cards(s)?.as_slice())
+    }
+}
+
+impl Display for Combination {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hand::{DoubleHand, Hand};
+    use crate::rules::RuleSet;
+
+    fn classify(s: &str) -> Result<Combination, Error> {
+        s.parse()
+    }
+
+    #[test]
+    fn test_classify() {
+        for (s, category, rank, length) in [
+            ("5", Category::Single, Card::Five, 1),
+            ("76543", Category::Sequence, Card::Three, 5),
+            ("3456789TJQKA", Category::Sequence, Card::Three, 12),
+            ("KK", Category::Pair, Card::King, 1),
+            ("334455", Category::PairSequence, Card::Three, 3),
+            ("999", Category::Triplet, Card::Nine, 1),
+            ("9995", Category::TripletSingle, Card::Nine, 1),
+            ("99955", Category::TripletPair, Card::Nine, 1),
+            ("333444", Category::TripletSequence, Card::Three, 2),
+            ("33344456", Category::TripletSequenceSingle, Card::Three, 2),
+            ("3334445566", Category::TripletSequencePair, Card::Three, 2),
+            ("22223D", Category::QuadSingle, Card::Two, 1),
+            ("22223344", Category::QuadPair, Card::Two, 1),
+            ("7777", Category::Bomb, Card::Seven, 1),
+            ("XD", Category::Rocket, Card::BlackJoker, 1),
+            ("XD34", Category::RocketSingle, Card::BlackJoker, 1),
+            ("33XD44", Category::RocketPair, Card::BlackJoker, 1),
+            // 三张6当作三张单牌带出
+            ("333444555666", Category::TripletSequence, Card::Three, 4),
+            (
+                "333444555777",
+                Category::TripletSequenceSingle,
+                Card::Three,
+                3,
+            ),
+        ] {
+            let combination = classify(s).unwrap();
+            assert_eq!(combination.category(), category, "{}", s);
+            assert_eq!(combination.rank(), rank, "{}", s);
+            assert_eq!(combination.length(), length, "{}", s);
+        }
+        assert_eq!(classify("9995").unwrap().kickers(), vec![Card::Five]);
+
+        for s in [
+            "", "34", "3456", "JQKA2", "3344", "2XD", "33334", "222333", "XD345",
+        ] {
+            assert!(
+                matches!(classify(s), Err(Error::InvalidCombination(_))),
+                "{}",
+                s
+            );
+        }
+
+        // 一副牌没有五张以上的炸弹和天王炸，两副牌没有火箭
+        for s in ["33333", "22222222", "XXDD", "XX"] {
+            assert!(classify(s).is_err(), "{}", s);
+        }
+        let double = |s: &str| Combination::classify_str::<DoubleHand>(s);
+        assert_eq!(double("33333").unwrap().0, Action::Bomb5(Card::Three));
+        assert_eq!(double("22222222").unwrap().0, Action::Bomb8(Card::Two));
+        assert_eq!(double("XXDD").unwrap().0, Action::KingBomb);
+        assert_eq!(double("XX").unwrap().0, Action::Pair(Card::BlackJoker));
+        for s in ["XD", "333333333", "XXX"] {
+            assert!(double(s).is_err(), "{}", s);
+        }
+        assert_eq!(
+            Combination::classify_str::<Hand>("3333").unwrap(),
+            classify("3333").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_every_follow_action() {
+        // 出牌生成的每一手牌都能识别回同样的牌
+        for s in [
+            "33344455566789",
+            "3344556677889TTJJ",
+            "22223333XD",
+            "345678999TJQKA2",
+        ] {
+            let hand: Hand = s.into();
+            for (action, _) in hand.arrange().follow(&Action::None, &RuleSet::default()) {
+                let mut cards: Vec<Card> = action.into();
+                let combination = Combination::try_from(cards.as_slice()).unwrap();
+                let mut classified = combination.cards();
+                cards.sort();
+                classified.sort();
+                assert_eq!(cards, classified, "{}", action);
+            }
+        }
+    }
+
+    #[test]
+    fn test_beats() {
+        for (a, b, expected) in [
+            ("4", "3", Ok(())),
+            ("3", "4", Err(BeatError::RankTooLow)),
+            ("3", "3", Err(BeatError::RankTooLow)),
+            ("44", "3", Err(BeatError::CategoryMismatch)),
+            ("45678", "34567", Ok(())),
+            ("456789", "34567", Err(BeatError::LengthMismatch)),
+            ("5553", "444K", Ok(())),
+            ("55533", "4442", Err(BeatError::CategoryMismatch)),
+            ("3333", "22", Ok(())),
+            ("2", "3333", Err(BeatError::BombPrecedence)),
+            ("4444", "3333", Ok(())),
+            ("3333", "4444", Err(BeatError::RankTooLow)),
+            ("XD", "2222", Ok(())),
+            ("2222", "XD", Err(BeatError::RocketPrecedence)),
+            ("XD34", "222256", Ok(())),
+            ("222256", "XD34", Err(BeatError::RankTooLow)),
+            ("XD34", "XD56", Err(BeatError::RankTooLow)),
+            ("XD3344", "222256", Err(BeatError::CategoryMismatch)),
+            ("3333", "XD3344", Ok(())),
+            ("XD3344", "3333", Err(BeatError::BombPrecedence)),
+        ] {
+            let (a, b) = (classify(a).unwrap(), classify(b).unwrap());
+            assert_eq!(a.beats(&b), expected, "{} {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_beats_matches_follow() {
+        // beats与出牌生成的“管上”一致
+        let hand: Hand = "334445556667789TTJJQQKA22XD".into();
+        let hand = hand.arrange();
+        let leads = hand.follow(&Action::None, &RuleSet::default());
+        for (lead, _) in leads.iter().step_by(97) {
+            let lead = Combination(*lead);
+            for (action, _) in hand.follow(&lead.0, &RuleSet::default()) {
+                if action != Action::None {
+                    assert_eq!(
+                        Combination(action).beats(&lead),
+                        Ok(()),
+                        "{} {}",
+                        action,
+                        lead
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+
+use crate::action::Action;
+use crate::card::Card;
+use crate::combination::Combination;
+use crate::game::{Config, Game, Outcome, State};
+use crate::hand::{Cards, Hand};
+use crate::Error;
+
+/// 没有设置samples时的抽样次数
+const SAMPLES: usize = 1000;
+
+/// 不知道对方手牌的局面：只知道我方手牌、还没出现的牌和每个对方的张数，轮到我方出牌
+#[derive(Clone, Debug)]
+pub struct Deal {
+    /// 我方（0号玩家）的手牌
+    pub hand: Hand,
+    /// 还没出现的牌（不含我方手牌），对方的手牌都从中发出
+    pub unseen: Hand,
+    /// 从1号玩家开始，每个对方的手牌张数
+    pub counts: Vec<u32>,
+    /// 地主的座位
+    pub landlord: u8,
+    /// 上家刚出的、我方需要应对的牌，None时我方自由出牌
+    pub lead: Option<Combination>,
+    /// 可能的发牌数超过samples时随机抽样samples次，否则全部枚举。默认1000，不能为0。
+    /// Game::robust不能抽样，按点数区分的发牌数超过samples时返回错误
+    pub samples: usize,
+    /// 抽样的随机种子，相同的种子得到相同的结果
+    pub seed: u64,
+}
+
+impl Default for Deal {
+    fn default() -> Self {
+        Deal {
+            hand: Hand::default(),
+            unseen: Hand::default(),
+            counts: Vec::new(),
+            landlord: 0,
+            lead: None,
+            samples: SAMPLES,
+            seed: 0,
+        }
+    }
+}
+
+/// 我方一种出法在各种发牌下的胜负统计
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveOdds {
+    /// 出的牌，“不要”时为空
+    pub cards: Vec<Card>,
+    /// 获胜的发牌数
+    pub wins: u64,
+    /// 判断出胜负的发牌数，超时的不计
+    pub deals: u64,
+}
+
+impl MoveOdds {
+    /// 胜率
+    pub fn rate(&self) -> f64 {
+        if self.deals == 0 {
+            0.0
+        } else {
+            self.wins as f64 / self.deals as f64
+        }
+    }
+}
+
+impl Deal {
+    /// 枚举或抽样对方的手牌，每种发牌下分别求解我方的每种出法，统计胜率。
+    /// 枚举时按区分花色的发牌数加权；config的时限和节点数对每次求解分别计算
+    pub fn win_rates(&self, config: &Config) -> Result<Vec<MoveOdds>, Error> {
+        self.check()?;
+        if self.samples == 0 {
+            return Err(Error::NoSamples);
+        }
+        let ranks = ranks(self.unseen);
+        let deals = match total(self.unseen.size(), &self.counts) {
+            // 区分花色的发牌数不超过samples，按点数枚举的也不会超过
+            Some(total) if total <= self.samples as u128 => {
+                self.enumerate(&ranks, self.samples).unwrap_or_default()
+            }
+            _ => self.sample(&ranks),
+        };
+
+        let lead = self.lead.map_or(Action::None, |c| c.0);
+        let moves = self.hand.arrange().follow(&lead, &config.rules);
+        let mut odds = moves
+            .iter()
+            .map(|&(action, _)| MoveOdds {
+                cards: action.into(),
+                wins: 0,
+                deals: 0,
+            })
+            .collect::<Vec<MoveOdds>>();
+
+        // 抽样时相同点数的发牌只求解一次
+        let mut solved: HashMap<Vec<Hand>, Vec<Option<bool>>> = HashMap::new();
+        for (opponents, weight) in deals {
+            if !solved.contains_key(&opponents) {
+                let root = self.root(opponents.clone())?;
+                let results = moves
+                    .iter()
+                    .map(|&(action, hand)| {
+                        if hand.is_empty() {
+                            return Some(true);
+                        }
+                        let game = Game::with_config(root.next(action, hand), config.clone());
+                        match game.outcome() {
+                            Outcome::Win => Some(true),
+                            Outcome::Loss => Some(false),
+                            Outcome::Unknown => None,
+                        }
+                    })
+                    .collect();
+                solved.insert(opponents.clone(), results);
+            }
+            let results = &solved[&opponents];
+            for (odds, won) in odds.iter_mut().zip(results.iter()) {
+                if let Some(won) = won {
+                    odds.deals += weight;
+                    if *won {
+                        odds.wins += weight;
+                    }
+                }
+            }
+        }
+        Ok(odds)
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.hand.is_empty() || self.counts.contains(&0) {
+            return Err(Error::Empty);
+        }
+        if self.counts.is_empty() {
+            return Err(Error::NoPlayers);
+        }
+        if self.counts.len() + 1 > Hand::MAX_PLAYERS {
+            return Err(Error::TooManyPlayers(self.counts.len() + 1));
+        }
+        let need = self.counts.iter().sum::<u32>();
+        if need > self.unseen.size() {
+            return Err(Error::NotEnoughUnseen(need, self.unseen.size()));
+        }
+        // 我方手牌和未出现的牌合起来不能超出一副牌，与创建局面时的检查相同
+        State::with_landlord(vec![self.hand, self.unseen], 0, 0)?;
+        Ok(())
+    }
+
+    /// 一种发牌下轮到我方的局面，有需要应对的牌时是上家刚出的
+    fn root(&self, opponents: Vec<Hand>) -> Result<State, Error> {
+        let mut hands = vec![self.hand];
+        hands.extend(opponents);
+        let mut state = State::with_landlord(hands, 0, self.landlord)?;
+        state.lead = self.lead.map_or(Action::None, |c| c.0);
+        Ok(state)
+    }
+
+    /// 所有可能的发牌，每种发牌是一个完整局面。按点数区分超过samples种时返回错误
+    pub(crate) fn worlds(&self) -> Result<Vec<State>, Error> {
+        self.check()?;
+        if self.samples == 0 {
+            return Err(Error::NoSamples);
+        }
+        self.enumerate(&ranks(self.unseen), self.samples)
+            .ok_or(Error::TooManyDeals(self.samples))?
+            .into_iter()
+            .map(|(opponents, _)| self.root(opponents))
+            .collect()
+    }
+
+    /// 按点数枚举所有发牌，返回各对方的手牌和对应的发牌数；超过limit种时停止，返回None
+    fn enumerate(&self, ranks: &[(Card, u32)], limit: usize) -> Option<Vec<(Vec<Hand>, u64)>> {
+        let mut deals = Vec::new();
+        let mut need = self.counts.clone();
+        let mut hands = vec![Hand::default(); need.len()];
+        let left = ranks.first().map(|r| r.1).unwrap_or_default();
+        distribute(
+            ranks, 0, 0, left, &mut need, &mut hands, 1, &mut deals, limit,
+        )
+        .then_some(deals)
+    }
+
+    /// 洗牌后按张数发给各个对方，重复samples次
+    fn sample(&self, ranks: &[(Card, u32)]) -> Vec<(Vec<Hand>, u64)> {
+        let mut cards = ranks
+            .iter()
+            .flat_map(|&(card, count)| std::iter::repeat_n(card, count as usize))
+            .collect::<Vec<Card>>();
+        let mut rng = fastrand::Rng::with_seed(self.seed);
+        (0..self.samples)
+            .map(|_| {
+                rng.shuffle(&mut cards);
+                let mut dealt = cards.iter();
+                let hands = self
+                    .counts
+                    .iter()
+                    .map(|&count| {
+                        let mut hand = Hand::default();
+                        for &card in dealt.by_ref().take(count as usize) {
+                            hand.draw_card(card);
+                        }
+                        hand
+                    })
+                    .collect();
+                (hands, 1)
+            })
+            .collect()
+    }
+}
+
+/// 各个点数还没出现的张数
+fn ranks(unseen: Hand) -> Vec<(Card, u32)> {
+    let mut ranks = Vec::new();
+    let mut card = Some(Card::Three);
+    while let Some(c) = card {
+        if unseen.card_count(c) > 0 {
+            ranks.push((c, unseen.card_count(c)));
+        }
+        card = c.plus();
+    }
+    ranks
+}
+
+/// 区分花色的发牌总数，太大时返回None
+fn total(mut size: u32, counts: &[u32]) -> Option<u128> {
+    let mut total = 1_u128;
+    for &count in counts {
+        total = total.checked_mul(binomial(size, count)?)?;
+        size -= count;
+    }
+    Some(total)
+}
+
+fn binomial(n: u32, k: u32) -> Option<u128> {
+    let mut result = 1_u128;
+    for i in 0..k {
+        result = result.checked_mul((n - i) as u128)? / (i + 1) as u128;
+    }
+    Some(result)
+}
+
+/// 把第rank种点数剩下的left张分给第seat个及之后的对方，分完再分下一种点数。
+/// 发牌超过limit种时停止，返回false
+#[allow(clippy::too_many_arguments)]
+fn distribute(
+    ranks: &[(Card, u32)],
+    rank: usize,
+    seat: usize,
+    left: u32,
+    need: &mut [u32],
+    hands: &mut [Hand],
+    weight: u64,
+    deals: &mut Vec<(Vec<Hand>, u64)>,
+    limit: usize,
+) -> bool {
+    if rank == ranks.len() {
+        if need.iter().all(|&n| n == 0) {
+            if deals.len() == limit {
+                return false;
+            }
+            deals.push((hands.iter().map(|h| h.arrange()).collect(), weight));
+        }
+        return true;
+    }
+    // 剩下的牌不够发
+    let rest = left + ranks[rank + 1..].iter().map(|r| r.1).sum::<u32>();
+    if rest < need.iter().sum::<u32>() {
+        return true;
+    }
+    if seat == need.len() {
+        let next = ranks.get(rank + 1).map(|r| r.1).unwrap_or_default();
+        return distribute(ranks, rank + 1, 0, next, need, hands, weight, deals, limit);
+    }
+
+    let card = ranks[rank].0;
+    let saved = hands[seat];
+    for k in 0..=left.min(need[seat]) {
+        if k > 0 {
+            hands[seat].draw_card(card);
+        }
+        need[seat] -= k;
+        let weight = weight * binomial(left, k).unwrap_or_default() as u64;
+        let complete = distribute(
+            ranks,
+            rank,
+            seat + 1,
+            left - k,
+            need,
+            hands,
+            weight,
+            deals,
+            limit,
+        );
+        need[seat] += k;
+        if !complete {
+            hands[seat] = saved;
+            return false;
+        }
+    }
+    hands[seat] = saved;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(odds: &[MoveOdds], cards: Vec<Card>) -> f64 {
+        odds.iter().find(|o| o.cards == cards).unwrap().rate()
+    }
+
+    #[test]
+    fn test_enumerate() {
+        // 对方一张牌，可能是A或4：出KK必胜，出3必败，出K只有对方是4时获胜
+        let deal = Deal {
+            hand: "KK3".into(),
+            unseen: "A4".into(),
+            counts: vec![1],
+            samples: 100,
+            ..Default::default()
+        };
+        let odds = deal.win_rates(&Config::default()).unwrap();
+        assert_eq!(odds.len(), 3);
+        assert_eq!(rate(&odds, vec![Card::King, Card::King]), 1.0);
+        assert_eq!(rate(&odds, vec![Card::Three]), 0.0);
+        assert_eq!(rate(&odds, vec![Card::King]), 0.5);
+
+        // 两张A的花色不同，对方拿到A的发牌数是4的两倍
+        let deal = Deal {
+            unseen: "AA4".into(),
+            ..deal
+        };
+        let odds = deal.win_rates(&Config::default()).unwrap();
+        let king = odds.iter().find(|o| o.cards == vec![Card::King]).unwrap();
+        assert_eq!((king.wins, king.deals), (1, 3));
+    }
+
+    #[test]
+    fn test_sample() {
+        let deal = Deal {
+            hand: "3344".into(),
+            unseen: "556677889".into(),
+            counts: vec![3],
+            samples: 20,
+            seed: 7,
+            ..Default::default()
+        };
+        // 发牌数C(9,3)=84，超过samples，改为抽样
+        let odds = deal.win_rates(&Config::default()).unwrap();
+        for o in &odds {
+            assert_eq!(o.deals, 20);
+            assert!((0.0..=1.0).contains(&o.rate()));
+        }
+        assert_eq!(odds, deal.win_rates(&Config::default()).unwrap());
+
+        let enumerated = Deal {
+            samples: 100,
+            ..deal.clone()
+        };
+        for o in enumerated.win_rates(&Config::default()).unwrap() {
+            assert_eq!(o.deals, 84);
+        }
+
+        assert!(matches!(
+            Deal {
+                counts: vec![10],
+                ..deal
+            }
+            .win_rates(&Config::default()),
+            Err(Error::NotEnoughUnseen(10, 9))
+        ));
+    }
+
+    #[test]
+    fn test_check() {
+        let deal = Deal {
+            hand: "KK3".into(),
+            unseen: "A4".into(),
+            counts: vec![1],
+            ..Default::default()
+        };
+        assert_eq!(deal.samples, 1000);
+        assert!(matches!(
+            Deal {
+                unseen: "KKK4".into(),
+                ..deal.clone()
+            }
+            .win_rates(&Config::default()),
+            Err(Error::OverlappingCards(Card::King, 5, 4))
+        ));
+        assert!(matches!(
+            Deal {
+                samples: 0,
+                ..deal.clone()
+            }
+            .win_rates(&Config::default()),
+            Err(Error::NoSamples)
+        ));
+        assert!(matches!(
+            Deal {
+                counts: Vec::new(),
+                ..deal
+            }
+            .win_rates(&Config::default()),
+            Err(Error::NoPlayers)
+        ));
+    }
+
+    #[test]
+    fn test_too_many_deals() {
+        // 对方各17张时发牌太多，不能枚举
+        let unseen = Hand::from("33445566778899TTJJQQKKAA22XD");
+        let deal = Deal {
+            hand: "3456789TJQKA2".into(),
+            unseen,
+            counts: vec![14, 14],
+            ..Default::default()
+        };
+        assert!(matches!(deal.worlds(), Err(Error::TooManyDeals(1000))));
+        assert!(matches!(
+            Game::robust(&deal, Config::default()),
+            Err(Error::TooManyDeals(1000))
+        ));
+
+        // 恰好samples种时可以枚举
+        let deal = Deal {
+            hand: "KK3".into(),
+            unseen: "A4".into(),
+            counts: vec![1],
+            samples: 2,
+            ..Default::default()
+        };
+        assert_eq!(deal.worlds().unwrap().len(), 2);
+        let deal = Deal { samples: 1, ..deal };
+        assert!(matches!(deal.worlds(), Err(Error::TooManyDeals(1))));
+    }
+
+    #[test]
+    fn test_lead() {
+        // 上家出了Q：管上K后对方是4时获胜，是A时必败；不要则对方一张牌直接出完
+        let deal = Deal {
+            hand: "KK3".into(),
+            unseen: "A4".into(),
+            counts: vec![1],
+            lead: Some("Q".parse().unwrap()),
+            ..Default::default()
+        };
+        let odds = deal.win_rates(&Config::default()).unwrap();
+        assert_eq!(odds.len(), 2);
+        assert_eq!(rate(&odds, vec![Card::King]), 0.5);
+        assert_eq!(rate(&odds, Vec::new()), 0.0);
+    }
+}
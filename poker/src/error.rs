@@ -1,9 +1,68 @@
 use thiserror::Error;
 
+use crate::card::{Card, SuitCard};
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("手牌不能为空")]
     Empty,
     #[error("Invalid card value: {0}")]
     InvalidCardValue(String),
+    #[error("第{position}个字符不是牌: {0}", position = .1 + 1)]
+    UnknownCard(char, usize),
+    #[error("第{position}个字符: {0}不能有花色", position = .1 + 1)]
+    SuitedJoker(Card, usize),
+    #[error("{0}不能超过{1}张")]
+    TooManyOfRank(Card, u32),
+    #[error("{0}只能有{1}张")]
+    TooManyJokers(Card, u32),
+    #[error("重复的牌: {0}")]
+    DuplicateCard(SuitCard),
+    #[error("玩家人数超出上限: {0}")]
+    TooManyPlayers(usize),
+    #[error("没有玩家")]
+    NoPlayers,
+    #[error("{0}号玩家没有手牌")]
+    EmptyHand(usize),
+    #[error("出牌的座位{0}超出玩家人数{1}")]
+    InvalidTurn(u8, usize),
+    #[error("地主的座位{0}超出玩家人数{1}")]
+    InvalidLandlord(u8, usize),
+    #[error("各家的{0}加起来有{1}张，超过了{2}张")]
+    OverlappingCards(Card, u32, u32),
+    #[error("各家手牌共{0}张，超过了{1}张")]
+    TooManyCards(u32, u32),
+    #[error("不是合法的牌型: {0}")]
+    InvalidCombination(String),
+    #[error("未出现的牌不够发给对方: 需要{0}张，只有{1}张")]
+    NotEnoughUnseen(u32, u32),
+    #[error("抽样次数不能为0")]
+    NoSamples,
+    #[error("可能的发牌超过{0}种，不能全部枚举")]
+    TooManyDeals(usize),
+    #[error("不是求解结果的存档")]
+    NotASave,
+    #[error("存档格式版本{0}不受支持，当前版本{1}")]
+    SaveVersion(u8, u8),
+    #[error("存档已损坏")]
+    CorruptSave,
+}
+
+/// 一手牌管不上另一手牌的原因
+#[derive(Error, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BeatError {
+    #[error("牌型不同")]
+    CategoryMismatch,
+    #[error("长度不同")]
+    LengthMismatch,
+    #[error("点数不够大")]
+    RankTooLow,
+    #[error("炸弹只能用更大的炸弹或火箭管上")]
+    BombPrecedence,
+    #[error("软炸弹管不上硬炸弹")]
+    HardBombPrecedence,
+    #[error("火箭最大，管不上")]
+    RocketPrecedence,
+    #[error("天王炸最大，管不上")]
+    KingBombPrecedence,
 }
@@ -0,0 +1,184 @@
+//! 把求解得到的方案树导出为JSON或Graphviz DOT，方便查看
+
+use std::fmt::Write;
+
+use indextree::NodeId;
+
+use crate::game::{Game, State};
+use crate::hand::Cards;
+
+/// 出这一手牌的座位，根节点没有
+fn mover<H: Cards>(state: &State<H>, root: bool) -> Option<u8> {
+    let players = state.hands().len() as u8;
+    (!root).then(|| (state.turn + players - 1) % players)
+}
+
+fn hands<H: Cards>(state: &State<H>) -> Vec<String> {
+    state.hands().iter().map(|h| h.to_string()).collect()
+}
+
+fn side<H: Cards>(state: &State<H>, seat: u8) -> &'static str {
+    if state.is_ours(seat) {
+        "ours"
+    } else {
+        "theirs"
+    }
+}
+
+impl<H: Cards> Game<H> {
+    /// 导出为嵌套的JSON：每个节点是一手牌，含出牌的座位、阵营（ours或theirs）、
+    /// 出的牌（不要时为空字符串）、出牌后各家的手牌和后续的应对。
+    /// 根节点的座位、阵营和出的牌为null；超过max_depth的节点不导出，被截断的节点truncated为true
+    pub fn to_json(&self, max_depth: Option<usize>) -> String {
+        let mut out = String::new();
+        if !self.root.is_removed(&self.arena) {
+            self.json_node(&mut out, self.root, 0, max_depth);
+        }
+        out
+    }
+
+    fn json_node(&self, out: &mut String, node_id: NodeId, depth: usize, max_depth: Option<usize>) {
+        let state = self.arena[node_id].get();
+        let quote = |s: String| format!("\"{s}\"");
+        let (seat, side, cards) = match mover(state, node_id == self.root) {
+            Some(seat) => (
+                seat.to_string(),
+                quote(side(state, seat).to_string()),
+                quote(state.action_cards().iter().map(|c| c.to_string()).collect()),
+            ),
+            None => ("null".to_string(), "null".to_string(), "null".to_string()),
+        };
+        let hands = hands(state).into_iter().map(quote).collect::<Vec<_>>();
+        let _ = write!(
+            out,
+            "{{\"seat\":{seat},\"side\":{side},\"cards\":{cards},\"hands\":[{}],\"children\":[",
+            hands.join(",")
+        );
+        let truncated = max_depth.is_some_and(|max| depth >= max);
+        let mut first = true;
+        for child in node_id.children(&self.arena) {
+            if truncated || child.is_removed(&self.arena) {
+                continue;
+            }
+            if !first {
+                out.push(',');
+            }
+            first = false;
+            self.json_node(out, child, depth + 1, max_depth);
+        }
+        out.push(']');
+        if truncated && node_id.children(&self.arena).next().is_some() {
+            out.push_str(",\"truncated\":true");
+        }
+        out.push('}');
+    }
+
+    /// 导出为Graphviz DOT：节点标出各家剩余的手牌，边标出出的牌；
+    /// 我方出牌用蓝色实线，对方出牌用红色虚线。超过max_depth的节点省略，用“…”表示
+    pub fn to_dot(&self, max_depth: Option<usize>) -> String {
+        let mut out = String::from("digraph game {\n    node [shape=box];\n");
+        if !self.root.is_removed(&self.arena) {
+            let mut next_id = 0;
+            self.dot_node(&mut out, self.root, 0, max_depth, &mut next_id);
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn dot_node(
+        &self,
+        out: &mut String,
+        node_id: NodeId,
+        depth: usize,
+        max_depth: Option<usize>,
+        next_id: &mut usize,
+    ) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let state = self.arena[node_id].get();
+        let label = hands(state)
+            .iter()
+            .enumerate()
+            .map(|(seat, hand)| format!("{seat}: {hand}"))
+            .collect::<Vec<_>>()
+            .join("\\n");
+        let _ = writeln!(out, "    n{id} [label=\"{label}\"];");
+
+        let mut children = node_id
+            .children(&self.arena)
+            .filter(|c| !c.is_removed(&self.arena))
+            .peekable();
+        if max_depth.is_some_and(|max| depth >= max) {
+            if children.peek().is_some() {
+                let _ = writeln!(out, "    n{id}_more [label=\"…\", shape=plaintext];");
+                let _ = writeln!(out, "    n{id} -> n{id}_more [style=dotted];");
+            }
+            return id;
+        }
+        for child in children {
+            let child_id = self.dot_node(out, child, depth + 1, max_depth, next_id);
+            let next = self.arena[child].get();
+            let style = match mover(next, false) {
+                Some(seat) if next.is_ours(seat) => "color=blue",
+                _ => "color=red, style=dashed",
+            };
+            let _ = writeln!(
+                out,
+                "    n{id} -> n{child_id} [label=\"{}\", {style}];",
+                next.action_string()
+            );
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game() -> Game {
+        // 我方先出5，对方只能不要，我方再出3出完
+        let game = Game::new(vec!["35", "4"], 0).unwrap();
+        assert!(game.pass());
+        game
+    }
+
+    #[test]
+    fn test_json() {
+        let json = game().to_json(None);
+        let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert!(value["seat"].is_null());
+        assert_eq!(value["hands"], serde_json::json!(["35", "4"]));
+        let ours = &value["children"][0];
+        assert_eq!(ours["seat"], 0);
+        assert_eq!(ours["side"], "ours");
+        assert_eq!(ours["hands"][0].as_str().unwrap().len(), 1);
+        let replies = ours["children"].as_array().unwrap();
+        assert!(replies
+            .iter()
+            .all(|r| r["side"] == "theirs" && r["seat"] == 1));
+        assert!(replies.iter().any(|r| r["cards"] == ""));
+
+        let json = game().to_json(Some(1));
+        let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(value["children"][0]["truncated"], true);
+        assert!(value["children"][0]["children"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = game().to_dot(None);
+        assert!(dot.starts_with("digraph game {"));
+        assert!(dot.contains("n0 [label=\"0: 35\\n1: 4\"];"), "{}", dot);
+        assert!(dot.contains("color=blue"));
+        assert!(dot.contains("label=\"不要\", color=red, style=dashed"));
+        assert!(!dot.contains("…"));
+
+        let dot = game().to_dot(Some(1));
+        assert!(dot.contains("n1 -> n1_more"), "{}", dot);
+        assert_eq!(dot.matches(" -> ").count(), 2);
+    }
+}
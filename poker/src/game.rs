@@ -1,10 +1,20 @@
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
 use indextree::{Arena, NodeId};
+use web_time::Instant;
 
 use crate::action::Action;
 use crate::card::Card;
-use crate::hand::Hand;
+use crate::combination::Combination;
+use crate::deal::Deal;
+use crate::hand::{Cards, Hand};
+use crate::robust::Robust;
+use crate::rules::RuleSet;
+use crate::search::Search;
 use crate::Error;
 
 pub(crate) enum StraightType {
@@ -19,65 +29,438 @@ pub(crate) enum Carry {
     Pair,
 }
 
+/// 置换表的键：各玩家手牌、需要应对的牌、连续“不要”的次数、当前回合谁出牌
+pub(crate) type Position<H> = (<H as Cards>::Hands, Action, u8, u8);
+
+/// 求解方式
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Mode {
+    /// 在内存中展开整棵搜索树，失败的分支随搜随删
+    #[default]
+    Tree,
+    /// 深度优先的与或搜索，只用置换表记录胜负，最后只生成必胜方案的子树
+    Search,
+    /// 与Search相同，但我方每一步都选择最少回合出完的出法，对方按最能拖延的应对
+    Shortest,
+}
+
+/// 求解进度回调，参数是当前的统计信息
+pub type Progress = Arc<dyn Fn(&Stats) + Send + Sync>;
+
+/// 求解配置
+#[derive(Clone, Default)]
+pub struct Config {
+    /// 求解方式
+    pub mode: Mode,
+    /// 我方回合保留所有必胜的出法，而不是只保留第一个找到的。
+    /// 方案树成指数增长，生成的节点超过2^18个后只再保留一种
+    pub all_wins: bool,
+    /// 求解的时限，从开始求解时计时
+    pub timeout: Option<Duration>,
+    /// 最多展开的节点数；方案树中生成的节点数另外计数，也不能超过它。
+    /// 置换表的内存由table_bytes限制
+    pub max_nodes: Option<usize>,
+    /// Search、Shortest模式和robust求解时置换表最多占用的字节数，满了清空重来；
+    /// None时分别最多记录2^20个局面、2^18个信息集。并行求解时每个出法分别计算
+    pub table_bytes: Option<usize>,
+    /// 取消标志，其它线程置为true后尽快停止求解
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// 求解过程中每展开一批节点回调一次
+    pub progress: Option<Progress>,
+    /// 根节点的各个出法分给线程池并行求解，max_nodes对每个出法分别计算
+    #[cfg(feature = "parallel")]
+    pub parallel: bool,
+    /// 出牌规则
+    pub rules: RuleSet,
+}
+
+/// 求解统计
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// 展开的节点数
+    pub expanded: usize,
+    /// 方案树中生成的节点数，包括之后删除的
+    pub created: usize,
+    /// 直接复用置换表中胜负的次数
+    pub transpositions: usize,
+    /// 对方出完牌后，回退删除的我方失败出法的节点数
+    pub rolled_back: usize,
+    /// 胜负已定后剪掉的节点数：Tree模式下删除的同层出法，Search模式下跳过的出法
+    pub pruned: usize,
+    /// 最大搜索深度
+    pub max_depth: usize,
+    /// 内存中节点数的峰值：Tree模式下是搜索树的节点，Search模式下是置换表的局面
+    pub peak_nodes: usize,
+    /// 耗时
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    /// 合并子局面的统计
+    #[cfg(feature = "parallel")]
+    pub(crate) fn absorb(&mut self, other: &Stats) {
+        self.expanded += other.expanded;
+        self.created += other.created;
+        self.transpositions += other.transpositions;
+        self.rolled_back += other.rolled_back;
+        self.pruned += other.pruned;
+        self.max_depth = self.max_depth.max(other.max_depth + 1);
+        self.peak_nodes += other.peak_nodes;
+    }
+}
+
+/// 求解结果
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// 我方必胜
+    Win,
+    /// 我方没有必胜的方案
+    Loss,
+    /// 预算用完或被取消，胜负未知
+    Unknown,
+}
+
+/// 检查截止时间、取消标志和回调进度的间隔节点数
+const BUDGET_CHECK_INTERVAL: usize = 1024;
+
+/// 保留所有必胜出法时，方案树生成的节点超过这个数后只再保留一种
+pub(crate) const ALL_WINS_NODES: usize = 1 << 18;
+
+/// 求解过程中消耗的预算和统计
+#[derive(Clone, Default)]
+pub(crate) struct Budget {
+    start: Option<Instant>,
+    deadline: Option<Instant>,
+    max_nodes: Option<usize>,
+    cancel: Vec<Arc<AtomicBool>>,
+    progress: Option<Progress>,
+    pub(crate) stats: Stats,
+    /// 预算已用完
+    pub(crate) exhausted: bool,
+}
+
+impl Budget {
+    pub(crate) fn new(config: &Config) -> Self {
+        let start = Instant::now();
+        Budget {
+            start: Some(start),
+            deadline: config.timeout.map(|t| start + t),
+            max_nodes: config.max_nodes,
+            cancel: config.cancel.iter().cloned().collect(),
+            progress: config.progress.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// 分给并行任务的预算，共享截止时间和取消标志，另外响应stop
+    #[cfg(feature = "parallel")]
+    pub(crate) fn worker(&self, stop: &Arc<AtomicBool>) -> Budget {
+        let mut cancel = self.cancel.clone();
+        cancel.push(stop.clone());
+        Budget {
+            cancel,
+            stats: Stats::default(),
+            exhausted: false,
+            ..self.clone()
+        }
+    }
+
+    /// 展开一个节点，预算用完返回false
+    pub(crate) fn spend(&mut self) -> bool {
+        if self.exhausted {
+            return false;
+        }
+        self.stats.expanded += 1;
+        self.check(self.stats.expanded)
+    }
+
+    /// 在方案树中生成一个节点，预算用完返回false
+    pub(crate) fn grow(&mut self) -> bool {
+        if self.exhausted {
+            return false;
+        }
+        self.stats.created += 1;
+        self.check(self.stats.created)
+    }
+
+    /// 计数达到count后检查节点数上限，每隔一批检查截止时间、取消标志并回调进度
+    fn check(&mut self, count: usize) -> bool {
+        if self.max_nodes.is_some_and(|max| count > max) {
+            self.exhausted = true;
+        } else if (count - 1).is_multiple_of(BUDGET_CHECK_INTERVAL) {
+            self.exhausted = self.deadline.is_some_and(|d| Instant::now() >= d)
+                || self.cancel.iter().any(|c| c.load(Ordering::Relaxed));
+            if let Some(progress) = &self.progress {
+                self.stats.elapsed = self.elapsed();
+                progress(&self.stats);
+            }
+        }
+        if self.exhausted {
+            log::debug!(
+                "求解预算已用完，已展开{}个节点，生成{}个节点",
+                self.stats.expanded,
+                self.stats.created
+            );
+        }
+        !self.exhausted
+    }
+
+    /// 记录搜索深度和内存中的节点数
+    pub(crate) fn observe(&mut self, depth: usize, nodes: usize) {
+        self.stats.max_depth = self.stats.max_depth.max(depth);
+        self.stats.peak_nodes = self.stats.peak_nodes.max(nodes);
+    }
+
+    /// 求解结束，记录耗时
+    pub(crate) fn finish(&mut self) {
+        self.stats.elapsed = self.elapsed();
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.start.map(|s| s.elapsed()).unwrap_or_default()
+    }
+}
+
+/// 局面，一副牌用Hand，两副牌用DoubleHand
 #[derive(Clone, Debug, PartialEq)]
-pub struct State {
-    /// 当前回合需要应对的牌
-    action: Action,
-    /// 玩家手牌
-    player: Vec<Hand>,
+pub struct State<H: Cards = Hand> {
+    /// 当前回合需要应对的牌，没有人“不要”时就是上一手出的牌
+    pub(crate) lead: Action,
+    /// 连续“不要”的次数
+    pub(crate) passes: u8,
+    /// 玩家手牌，只有前players个有效
+    pub(crate) player: H::Hands,
+    /// 玩家人数
+    players: u8,
+    /// 地主的座位，其余玩家都是农民
+    landlord: u8,
     /// 当前回合谁出牌
-    turn: u8,
+    pub(crate) turn: u8,
     /// 当前方案验证已通过
-    pass: bool,
+    pub(crate) pass: bool,
+    /// 已经展开过下一级节点
+    pub(crate) expanded: bool,
 }
 
-impl Display for State {
+impl<H: Cards> Display for State<H> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut hands = Vec::new();
-        for hand in &self.player {
+        for hand in self.hands() {
             hands.push(hand.to_string());
         }
         write!(
             f,
-            "Action: {}, Hands: {:?}, Turn: {}, Pass: {}",
-            self.action, hands, self.turn, self.pass
+            "Action: {}, Lead: {}, Hands: {:?}, Landlord: {}, Turn: {}, Pass: {}",
+            self.action(),
+            self.lead,
+            hands,
+            self.landlord,
+            self.turn,
+            self.pass
         )
     }
 }
 
+/// 检查玩家人数、座位和每个点数在各家手牌中的张数
+fn validate<H: Cards>(hands: &[H], turn: u8, landlord: u8) -> Result<(), Error> {
+    let players = hands.len();
+    if players == 0 {
+        return Err(Error::NoPlayers);
+    }
+    if players > H::MAX_PLAYERS {
+        return Err(Error::TooManyPlayers(players));
+    }
+    if turn as usize >= players {
+        return Err(Error::InvalidTurn(turn, players));
+    }
+    if landlord as usize >= players {
+        return Err(Error::InvalidLandlord(landlord, players));
+    }
+    if let Some(i) = hands.iter().position(|h| h.is_empty()) {
+        return Err(Error::EmptyHand(i));
+    }
+    let decks = H::DECKS as u32;
+    let total = hands.iter().map(|h| h.size()).sum::<u32>();
+    if total > 54 * decks {
+        return Err(Error::TooManyCards(total, 54 * decks));
+    }
+    let mut card = Some(Card::Three);
+    while let Some(c) = card {
+        let limit = if c >= Card::BlackJoker {
+            decks
+        } else {
+            4 * decks
+        };
+        let mut total = 0;
+        for hand in hands {
+            let count = hand.card_count(c);
+            if count > limit && c >= Card::BlackJoker {
+                return Err(Error::TooManyJokers(c, limit));
+            }
+            if count > limit {
+                return Err(Error::TooManyOfRank(c, limit));
+            }
+            total += count;
+        }
+        if total > limit {
+            return Err(Error::OverlappingCards(c, total, limit));
+        }
+        card = c.plus();
+    }
+    Ok(())
+}
+
 impl State {
+    /// 0号玩家（我方）做地主，其余玩家都是对手
     pub fn new(player_hand: Vec<impl Into<Hand>>, turn: u8) -> Result<State, Error> {
-        let mut player = Vec::new();
-        for s in player_hand {
-            let hand: Hand = s.into();
-            player.push(hand.arrange());
+        State::with_landlord(player_hand, turn, 0)
+    }
+
+    /// 指定地主的座位，农民同属一方，任一农民出完牌即农民获胜
+    pub fn with_landlord(
+        player_hand: Vec<impl Into<Hand>>,
+        turn: u8,
+        landlord: u8,
+    ) -> Result<State, Error> {
+        State::from_hands(player_hand, turn, landlord)
+    }
+}
+
+impl<H: Cards> State<H> {
+    /// 指定手牌类型的局面，如State::<DoubleHand>::from_hands，检查各家手牌加起来不超出这几副牌
+    pub fn from_hands(
+        player_hand: Vec<impl Into<H>>,
+        turn: u8,
+        landlord: u8,
+    ) -> Result<Self, Error> {
+        let hands = player_hand
+            .into_iter()
+            .map(|s| s.into())
+            .collect::<Vec<H>>();
+        validate(&hands, turn, landlord)?;
+        let players = hands.len() as u8;
+        let mut player = H::Hands::default();
+        for (i, hand) in hands.into_iter().enumerate() {
+            player.as_mut()[i] = hand.arrange();
         }
 
         Ok(State {
-            action: Action::None,
+            lead: Action::None,
+            passes: 0,
             player,
+            players,
+            landlord,
             turn,
             pass: false,
+            expanded: false,
         })
     }
 
+    /// 只有我方手牌已知、其他玩家手牌置空的公开局面，如robust方案中的节点。
+    /// 只检查我方手牌和座位
+    pub(crate) fn public(hand: H, players: u8, turn: u8, landlord: u8) -> Result<Self, Error> {
+        let mut state = State::from_hands(vec![hand], 0, 0)?;
+        if players as usize > H::MAX_PLAYERS {
+            return Err(Error::TooManyPlayers(players as usize));
+        }
+        if turn >= players {
+            return Err(Error::InvalidTurn(turn, players as usize));
+        }
+        if landlord >= players {
+            return Err(Error::InvalidLandlord(landlord, players as usize));
+        }
+        state.players = players;
+        state.turn = turn;
+        state.landlord = landlord;
+        Ok(state)
+    }
+
+    /// 上一手出的牌，不单独保存：有人“不要”时是不要，否则就是需要应对的牌
+    pub(crate) fn action(&self) -> Action {
+        if self.passes == 0 {
+            self.lead
+        } else {
+            Action::None
+        }
+    }
+
     pub fn action_string(&self) -> String {
-        self.action.to_string()
+        self.action().to_string()
     }
 
     pub fn action_cards(&self) -> Vec<Card> {
-        self.action.into()
+        self.action().into()
     }
 
     pub fn turn(&self) -> u8 {
         self.turn
     }
+
+    /// 当前回合需要管上的牌，自由出牌时为None
+    pub fn lead(&self) -> Option<Combination> {
+        (self.lead != Action::None).then_some(Combination(self.lead))
+    }
+
+    pub fn landlord(&self) -> u8 {
+        self.landlord
+    }
+
+    /// 各玩家的手牌
+    pub(crate) fn hands(&self) -> &[H] {
+        &self.player.as_ref()[..self.players as usize]
+    }
+
+    /// 指定座位是否与0号玩家（我方）同一阵营
+    pub fn is_ours(&self, seat: u8) -> bool {
+        (seat == self.landlord) == (self.landlord == 0)
+    }
+
+    /// 当前回合是否轮到我方出牌
+    pub fn our_turn(&self) -> bool {
+        self.is_ours(self.turn)
+    }
+
+    /// 当前玩家打出action后剩余hand，得到的下一个局面
+    pub(crate) fn next(&self, action: Action, hand: H) -> Self {
+        let players = self.players;
+        let mut player = self.player;
+        player.as_mut()[self.turn as usize] = hand;
+        // 其他玩家都“不要”，出牌权回到最后出牌的玩家
+        let (lead, passes) = match action {
+            Action::None if self.passes + 2 >= players => (Action::None, 0),
+            Action::None => (self.lead, self.passes + 1),
+            _ => (action, 0),
+        };
+        State {
+            lead,
+            passes,
+            player,
+            players,
+            landlord: self.landlord,
+            turn: (self.turn + 1) % players,
+            pass: hand.is_empty() && self.our_turn(),
+            expanded: false,
+        }
+    }
+
+    /// 手牌在创建时已经整理（arrange），出牌时从高位移除，始终保持无视花色的规范形式
+    pub(crate) fn position(&self) -> Position<H> {
+        (self.player, self.lead, self.passes, self.turn)
+    }
 }
 
 #[derive(Clone)]
-pub struct Game {
-    pub arena: Arena<State>,
+pub struct Game<H: Cards = Hand> {
+    pub arena: Arena<State<H>>,
     pub root: NodeId,
+    /// 置换表：已证明胜负的局面，胜局记录已验证通过的节点，败局为None
+    table: HashMap<Position<H>, Option<NodeId>>,
+    /// 已展开、尚未证明胜负的节点
+    open: HashMap<NodeId, Position<H>>,
+    pub(crate) config: Config,
+    pub(crate) budget: Budget,
+    /// 这是对方的反驳方案：对方只保留一种应对，我方保留所有出法
+    pub(crate) refutation: bool,
 }
 
 impl Game {
@@ -85,14 +468,108 @@ impl Game {
         Ok(Game::from_state(State::new(player_hand, turn)?))
     }
 
-    pub fn from_state(state: State) -> Self {
+    /// 不知道对方手牌时，求解不论未出现的牌怎样分给其他玩家都必胜的方案。
+    /// 我方只根据公开信息出牌，方案中其他玩家的手牌置空；config只使用规则和时限、节点数等预算
+    pub fn robust(deal: &Deal, config: Config) -> Result<Game, Error> {
+        let worlds = deal.worlds()?;
+        let mut robust = Robust::new(&config, Budget::new(&config));
         let mut arena = Arena::new();
-        let root = arena.new_node(state);
-        let mut game = Game { arena, root };
-        game.play();
+        let root = robust.strategy(&mut arena, worlds);
+        let mut game = Game::from_parts(arena, root, config, robust.budget);
+        game.budget.finish();
+        Ok(game)
+    }
+}
+
+impl<H: Cards> Game<H> {
+    pub fn from_state(state: State<H>) -> Self {
+        Game::with_mode(state, Mode::Tree)
+    }
+
+    pub fn with_mode(state: State<H>, mode: Mode) -> Self {
+        Game::with_config(
+            state,
+            Config {
+                mode,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn with_config(state: State<H>, config: Config) -> Self {
+        let budget = Budget::new(&config);
+        #[cfg(feature = "parallel")]
+        if config.parallel {
+            return crate::parallel::solve(state, config, budget);
+        }
+        Game::with_budget(state, config, budget)
+    }
+
+    pub(crate) fn with_budget(state: State<H>, config: Config, budget: Budget) -> Self {
+        let mut arena = Arena::new();
+        let (root, budget) = match config.mode {
+            Mode::Tree => (arena.new_node(state), budget),
+            Mode::Search | Mode::Shortest => {
+                let mut search = Search::new(&config, budget);
+                let root = search.strategy(&mut arena, state);
+                (root, search.budget)
+            }
+        };
+        let mut game = Game::from_parts(arena, root, config, budget);
+        if game.config.mode == Mode::Tree {
+            game.play();
+        }
+        game.budget.finish();
         game
     }
 
+    pub(crate) fn from_parts(
+        arena: Arena<State<H>>,
+        root: NodeId,
+        config: Config,
+        budget: Budget,
+    ) -> Self {
+        Game {
+            arena,
+            root,
+            table: HashMap::new(),
+            open: HashMap::new(),
+            config,
+            budget,
+            refutation: false,
+        }
+    }
+
+    /// 我方必败时，求解对方的反驳方案：我方每种出法下，对方都有一种应对让我方必败。
+    /// 返回的Game中pass表示反驳方案已验证，action返回对方的应对和我方的所有出法
+    pub fn refutation(&self) -> Option<Self> {
+        if self.outcome() != Outcome::Loss {
+            return None;
+        }
+        let mut state = self.arena[self.root].get().clone();
+        state.pass = false;
+        state.expanded = false;
+
+        let config = self.config.clone();
+        let mut search = Search::new(&config, Budget::new(&config));
+        let mut arena = Arena::new();
+        let root = search.refutation(&mut arena, state);
+        let mut game = Game::from_parts(arena, root, config, search.budget);
+        game.budget.finish();
+        game.refutation = true;
+        Some(game)
+    }
+
+    /// 是否是对方的反驳方案
+    pub fn is_refutation(&self) -> bool {
+        self.refutation
+    }
+
+    /// 当前节点只保留了一种出法（必胜方案中的我方，反驳方案中的对方）
+    fn single_move(&self, state: &State<H>) -> bool {
+        state.our_turn() != self.refutation
+    }
+
     pub fn pass(&self) -> bool {
         self.arena
             .get(self.root)
@@ -100,7 +577,25 @@ impl Game {
             .unwrap_or_default()
     }
 
-    // 返回当前节点下的，我方出牌和对方所有出牌可能
+    pub fn stats(&self) -> Stats {
+        self.budget.stats
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        if self.pass() {
+            if self.refutation {
+                Outcome::Loss
+            } else {
+                Outcome::Win
+            }
+        } else if self.budget.exhausted {
+            Outcome::Unknown
+        } else {
+            Outcome::Loss
+        }
+    }
+
+    // 返回当前节点下的，我方出牌和对方所有出牌可能；反驳方案中是对方出牌和我方所有出牌可能
     pub fn action(&self, node_id: Option<NodeId>) -> (Vec<Card>, Vec<(NodeId, Vec<Card>)>) {
         let mut our_played_cards = Vec::new();
         let mut opponent_choice = Vec::new();
@@ -118,7 +613,7 @@ impl Game {
         if let Some(state) = self.arena.get(current_node_id) {
             log::trace!("turn: {}", state.get().turn());
             //我方出牌的状态
-            if state.get().turn() == 0 {
+            if self.single_move(state.get()) {
                 // 从下一个节点取我方的action
                 if let Some(n) = current_node_id.children(&self.arena).next() {
                     current_node_id = n;
@@ -144,6 +639,38 @@ impl Game {
         (our_played_cards, opponent_choice)
     }
 
+    /// 我方回合所有必胜的出法，需要以Config::all_wins求解，否则只有一种
+    pub fn winning_moves(&self, node_id: Option<NodeId>) -> Vec<(NodeId, Vec<Card>)> {
+        let node_id = node_id.unwrap_or(self.root);
+        if node_id.is_removed(&self.arena)
+            || self.refutation
+            || !self.arena[node_id].get().our_turn()
+        {
+            return Vec::new();
+        }
+
+        node_id
+            .children(&self.arena)
+            .map(|n| (n, self.arena[n].get().action_cards()))
+            .collect()
+    }
+
+    /// 方案中最坏情况下我方还需要的回合数
+    #[cfg(any(test, feature = "parallel"))]
+    pub(crate) fn our_turns(&self, node_id: NodeId) -> u8 {
+        let turns = node_id
+            .children(&self.arena)
+            .map(|n| self.our_turns(n))
+            .max()
+            .unwrap_or_default();
+        let state = self.arena[node_id].get();
+        if state.our_turn() && node_id.children(&self.arena).next().is_some() {
+            turns + 1
+        } else {
+            turns
+        }
+    }
+
     pub fn print(&self) {
         self.print_child(self.root, self.root)
     }
@@ -165,22 +692,28 @@ impl Game {
     }
 }
 
-impl Game {
+impl<H: Cards> Game<H> {
     fn play(&mut self) {
         let mut next_node_id = Some(self.root);
         while let Some(node_id) = next_node_id {
             let state = self.arena.get(node_id).unwrap().get();
-            next_node_id = if !state.player.is_empty() {
-                // node_id: 当前节点有player
+            if !state.expanded && !self.budget.spend() {
+                break;
+            }
+            next_node_id = if !state.expanded {
+                // node_id: 当前节点还没有展开
                 self.expand(node_id)
-            } else if state.turn == 0 {
-                // node_id: 当前节点已经展开过, turn: 0
+            } else if state.our_turn() {
+                // node_id: 当前节点已经展开过, 轮到我方
                 self.expand_player1(node_id)
             } else {
-                // node_id: 当前节点已经展开过, turn != 0
+                // node_id: 当前节点已经展开过, 轮到对方
                 self.expand_other_player(node_id)
             };
         }
+        self.budget.observe(0, self.arena.live_count());
+        self.table = HashMap::new();
+        self.open = HashMap::new();
     }
 
     fn expand_player1(&mut self, node_id: NodeId) -> Option<NodeId> {
@@ -190,14 +723,25 @@ impl Game {
             return self.rollback_to_last_player1(node_id);
         }
 
-        return if let Some(passed) = children
+        // 保留所有必胜出法时，逐个验证每个分支
+        if self.all_wins() {
+            return match children
+                .iter()
+                .find(|&&n| !self.arena.get(n).unwrap().get().pass)
+            {
+                Some(todo) => Some(*todo),
+                None => self.prove(node_id),
+            };
+        }
+
+        if let Some(passed) = children
             .iter()
             .find(|&&n| self.arena.get(n).unwrap().get().pass)
         {
             self.delete_siblings(node_id, *passed)
         } else {
             Some(children[0])
-        };
+        }
     }
 
     fn expand_other_player(&mut self, node_id: NodeId) -> Option<NodeId> {
@@ -205,33 +749,96 @@ impl Game {
             .children(&self.arena)
             .find(|&n| !self.arena.get(n).unwrap().get().pass);
         if todo.is_none() {
-            self.arena.get_mut(node_id).unwrap().get_mut().pass = true;
-            node_id.ancestors(&self.arena).nth(1)
+            self.prove(node_id)
         } else {
             todo
         }
     }
 
+    /// 当前节点验证通过，记入置换表，返回上一级节点
+    fn prove(&mut self, node_id: NodeId) -> Option<NodeId> {
+        self.arena.get_mut(node_id).unwrap().get_mut().pass = true;
+        if let Some(position) = self.open.remove(&node_id) {
+            self.table.insert(position, Some(node_id));
+        }
+        node_id.ancestors(&self.arena).nth(1)
+    }
+
+    /// 还能保留所有必胜出法
+    fn all_wins(&self) -> bool {
+        self.config.all_wins && self.budget.stats.created < ALL_WINS_NODES
+    }
+
+    /// 复制已验证通过的相同局面的子树，预算用完时返回false，复制的子树不完整。
+    /// 我方的必胜出法都已验证，节点太多时只复制第一种
+    fn copy_children(&mut self, from: NodeId, to: NodeId) -> bool {
+        let ours = self.arena[from].get().our_turn();
+        for (i, child) in from
+            .children(&self.arena)
+            .collect::<Vec<NodeId>>()
+            .into_iter()
+            .enumerate()
+        {
+            if ours && i > 0 && !self.all_wins() {
+                break;
+            }
+            if !self.budget.grow() {
+                return false;
+            }
+            let state = self.arena.get(child).unwrap().get().clone();
+            let copied = self.arena.new_node(state);
+            to.append(copied, &mut self.arena);
+            if !self.copy_children(child, copied) {
+                return false;
+            }
+        }
+        true
+    }
+
     /// 展开下一级节点
     fn expand(&mut self, node_id: NodeId) -> Option<NodeId> {
-        let mut state = self.arena.get(node_id)?.get().clone();
+        let state = self.arena.get(node_id)?.get().clone();
         let mut next_node_id = None;
         let turn = state.turn as usize;
+        let ours = state.our_turn();
 
-        let Some(hand) = state.player.get(turn).filter(|&h|!h.is_empty()) else {
+        let Some(hand) = state.player.as_ref().get(turn).filter(|&h| !h.is_empty()) else {
             log::error!("手牌为空？ {}", state);
             return None;
         };
 
-        for (action, hand) in hand.follow(&state.action) {
-            state.player[turn] = hand;
+        // 不同出牌顺序到达的相同局面，直接复用已证明的胜负
+        let position = state.position();
+        match self.table.get(&position) {
+            Some(None) => {
+                log::trace!("node: {}, transposition: lost", node_id);
+                self.budget.stats.transpositions += 1;
+                self.arena.get_mut(node_id).unwrap().get_mut().expanded = true;
+                return self.rollback_to_last_player1(node_id);
+            }
+            Some(Some(won)) if !won.is_removed(&self.arena) => {
+                log::trace!("node: {}, transposition: won by {}", node_id, won);
+                self.budget.stats.transpositions += 1;
+                let won = *won;
+                self.arena.get_mut(node_id).unwrap().get_mut().expanded = true;
+                if !self.copy_children(won, node_id) {
+                    return None;
+                }
+                return self.prove(node_id);
+            }
+            _ => {
+                self.open.insert(node_id, position);
+            }
+        }
+
+        let all_wins = ours && self.all_wins();
+        for (action, hand) in hand.follow(&state.lead, &self.config.rules) {
+            // 预算用完时停止，方案不完整，根节点不会被证明
+            if !self.budget.grow() {
+                return None;
+            }
             let pass = hand.is_empty();
-            let child = self.arena.new_node(State {
-                action,
-                player: state.player.clone(),
-                turn: (state.turn + 1) % state.player.len() as u8,
-                pass: pass && turn == 0,
-            });
+            let child = self.arena.new_node(state.next(action, hand));
             node_id.append(child, &mut self.arena);
             log::trace!(
                 "parent: {}, child:{}, node:{}",
@@ -240,32 +847,53 @@ impl Game {
                 self.arena.get(child).unwrap().get()
             );
 
-            if pass {
-                self.arena.get_mut(node_id).unwrap().get_mut().player = Vec::new();
-                return if turn != 0 {
+            if pass && !all_wins {
+                self.arena.get_mut(node_id).unwrap().get_mut().expanded = true;
+                return if !ours {
                     self.rollback_to_last_player1(node_id)
                 } else {
                     self.delete_siblings(node_id, child)
                 };
             }
 
-            if next_node_id.is_none() {
+            if next_node_id.is_none() && !pass {
                 next_node_id = Some(child);
             }
         }
 
-        // if !node_id.is_removed(&self.arena) {
-        self.arena.get_mut(node_id).unwrap().get_mut().player = Vec::new();
-        // }
+        self.arena.get_mut(node_id).unwrap().get_mut().expanded = true;
+        self.budget.observe(
+            node_id.ancestors(&self.arena).count(),
+            self.arena.live_count(),
+        );
 
+        if all_wins {
+            // 所有出法都能直接出完时，回到当前节点完成验证
+            return next_node_id.or(Some(node_id));
+        }
         next_node_id
     }
 
-    /// 其它玩家已经出光啦,此路不通,找到player1的上一个Action
+    /// 对方已经出光啦,此路不通,找到我方（含队友）的上一个Action
     fn rollback_to_last_player1(&mut self, node_id: NodeId) -> Option<NodeId> {
-        let last_node_id = node_id
-            .ancestors(&self.arena)
-            .find(|n| self.arena.get(*n).unwrap().get().turn == 1)?;
+        let mut last_node_id = None;
+        for n in node_id.ancestors(&self.arena) {
+            // 回退路径上的局面都已证明必败
+            if let Some(position) = self.open.remove(&n) {
+                self.table.insert(position, None);
+            }
+            let ours = self
+                .arena
+                .get(n)
+                .and_then(|n| n.parent())
+                .map(|p| self.arena.get(p).unwrap().get().our_turn())
+                .unwrap_or_default();
+            if ours {
+                last_node_id = Some(n);
+                break;
+            }
+        }
+        let last_node_id = last_node_id?;
 
         // 找到错误的Action的上一个节点，准备从此节点重新选择
         let next_node_id = last_node_id.ancestors(&self.arena).nth(1);
@@ -279,12 +907,13 @@ impl Game {
 
         //不要删掉root节点
         if self.root != last_node_id {
+            self.budget.stats.rolled_back += last_node_id.descendants(&self.arena).count();
             last_node_id.remove_subtree(&mut self.arena);
         }
         next_node_id
     }
 
-    /// player1已经出光啦, 删除同层的其它出法
+    /// 我方（含队友）已经出光啦, 删除同层的其它出法
     fn delete_siblings(
         &mut self,
         current_node_id: NodeId,
@@ -297,6 +926,7 @@ impl Game {
             .iter()
             .for_each(|n| {
                 log::trace!("passed_node_id: {}, delete sibling: {}", passed_node_id, n);
+                self.budget.stats.pruned += n.descendants(&self.arena).count();
                 n.remove(&mut self.arena)
             });
         passed_node_id
@@ -306,17 +936,18 @@ impl Game {
             .iter()
             .for_each(|n| {
                 log::trace!("passed_node_id: {}, delete sibling: {}", passed_node_id, n);
+                self.budget.stats.pruned += n.descendants(&self.arena).count();
                 n.remove(&mut self.arena)
             });
 
-        self.arena.get_mut(current_node_id).unwrap().get_mut().pass = true;
-        current_node_id.ancestors(&self.arena).nth(1)
+        self.prove(current_node_id)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hand::DoubleHand;
 
     #[test]
     fn test_state_play() {
@@ -330,4 +961,599 @@ mod tests {
         game.print();
         assert!(!game.pass());
     }
+
+    #[test]
+    fn test_landlord_and_peasants() {
+        // 我方是农民，地主管不上5，队友出完即获胜
+        let state = State::with_landlord(vec!["35", "4", "6"], 0, 1).unwrap();
+        let game = Game::from_state(state);
+        game.print();
+        assert!(game.pass());
+
+        // 我方是地主，农民任一人出完即失败
+        let state = State::with_landlord(vec!["35", "4", "6"], 0, 0).unwrap();
+        let game = Game::from_state(state);
+        assert!(!game.pass());
+
+        let state = State::with_landlord(vec!["3", "KK", "4"], 1, 1).unwrap();
+        assert!(!Game::from_state(state).pass());
+    }
+
+    #[test]
+    fn test_compact_state() {
+        // 节点不再单独分配手牌，展开后手牌仍然保留。原来的节点是上一手牌、
+        // 堆上的Vec<Hand>、turn和pass，两个玩家时就比现在的局面大
+        let baseline = |players: usize| {
+            std::mem::size_of::<(Action, Vec<Hand>, u8, bool)>()
+                + players * std::mem::size_of::<Hand>()
+        };
+        assert!(std::mem::size_of::<State>() < baseline(2));
+        let game = Game::new(vec!["3344", "55"], 0).unwrap();
+        let root = game.arena[game.root].get();
+        assert!(root.expanded);
+        assert_eq!(root.hands().len(), 2);
+        // 一副牌最多三人，两副牌最多四人
+        assert!(matches!(
+            State::new(vec!["3", "4", "5", "6"], 0),
+            Err(Error::TooManyPlayers(4))
+        ));
+        assert!(matches!(
+            State::<DoubleHand>::from_hands(vec!["3", "4", "5", "6", "7"], 0, 0),
+            Err(Error::TooManyPlayers(5))
+        ));
+    }
+
+    #[test]
+    fn test_validate() {
+        let empty: Vec<&str> = Vec::new();
+        assert!(matches!(State::new(empty, 0), Err(Error::NoPlayers)));
+        assert!(matches!(
+            State::new(vec!["3", "4"], 2),
+            Err(Error::InvalidTurn(2, 2))
+        ));
+        assert!(matches!(
+            State::with_landlord(vec!["3", "4"], 0, 3),
+            Err(Error::InvalidLandlord(3, 2))
+        ));
+        assert!(matches!(
+            State::new(vec!["3", ""], 0),
+            Err(Error::EmptyHand(1))
+        ));
+        assert!(matches!(
+            State::new(vec!["XX", "4"], 0),
+            Err(Error::TooManyJokers(Card::BlackJoker, 1))
+        ));
+        // 同一张牌不能同时在两家手里
+        assert!(matches!(
+            State::new(vec!["333", "34", "3"], 0),
+            Err(Error::OverlappingCards(Card::Three, 5, 4))
+        ));
+        assert!(matches!(
+            State::new(vec!["XD", "D"], 0),
+            Err(Error::OverlappingCards(Card::RedJoker, 2, 1))
+        ));
+        let all = "3333444455556666777788889999TTTTJJJJQQQQKKKKAAAA2222XD";
+        assert!(matches!(
+            State::new(vec![all, "3"], 0),
+            Err(Error::TooManyCards(55, 54))
+        ));
+        assert!(State::new(vec![all], 0).is_ok());
+        // 两副牌时可以有八张同点数和两张大王
+        assert!(State::<DoubleHand>::from_hands(vec!["33333", "333D", "D"], 0, 0).is_ok());
+        assert!(matches!(
+            State::<DoubleHand>::from_hands(vec!["XXX", "4"], 0, 0),
+            Err(Error::TooManyJokers(Card::BlackJoker, 2))
+        ));
+        assert!(matches!(
+            State::<DoubleHand>::from_hands(vec!["33333", "3333"], 0, 0),
+            Err(Error::OverlappingCards(Card::Three, 9, 8))
+        ));
+    }
+
+    #[test]
+    fn test_big_combinations() {
+        // 对方有火箭，只有一手出完才能赢
+        for hand in ["3334445566", "22223344", "222234", "3334445556667789"] {
+            let state = State::new(vec![hand, "XD"], 0).unwrap();
+            for mode in [Mode::Tree, Mode::Search] {
+                assert!(Game::with_mode(state.clone(), mode).pass(), "{}", hand);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lead_after_two_passes() {
+        let mut arena = Arena::new();
+        let root = arena.new_node(State::with_landlord(vec!["53", "4", "4"], 0, 1).unwrap());
+        let mut game = Game {
+            arena,
+            root,
+            table: HashMap::new(),
+            open: HashMap::new(),
+            config: Config::default(),
+            budget: Budget::default(),
+            refutation: false,
+        };
+        game.expand(root);
+
+        // 我方出5，地主不要，农民队友仍需管5
+        let five = root
+            .children(&game.arena)
+            .find(|n| game.arena[*n].get().action() == Action::Single(Card::Five))
+            .unwrap();
+        game.expand(five);
+        let passed = five.children(&game.arena).next().unwrap();
+        let state = game.arena[passed].get();
+        assert_eq!(state.action(), Action::None);
+        assert_eq!(state.lead, Action::Single(Card::Five));
+        assert_eq!(state.turn, 2);
+
+        // 两家都不要，出牌权回到我方
+        game.expand(passed);
+        let back = passed.children(&game.arena).next().unwrap();
+        let state = game.arena[back].get();
+        assert_eq!(state.lead, Action::None);
+        assert_eq!(state.turn, 0);
+        assert!(game.arena[root].get().expanded);
+    }
+
+    /// 验证通过的方案中，我方节点只保留一种出法，叶子节点都是我方出完牌
+    fn assert_strategy(game: &Game, node_id: NodeId) {
+        let state = game.arena[node_id].get();
+        assert!(state.pass, "{}", state);
+        let children = node_id.children(&game.arena).collect::<Vec<NodeId>>();
+        if children.is_empty() {
+            let parent = game.arena[node_id].parent().unwrap();
+            assert!(game.arena[parent].get().our_turn());
+        } else if state.our_turn() && !game.config.all_wins {
+            assert_eq!(children.len(), 1);
+        }
+        for child in children {
+            assert_strategy(game, child);
+        }
+    }
+
+    /// 不建树的朴素极小化极大搜索，用来校验Game的结果
+    fn brute_force<H: Cards>(state: &State<H>) -> bool {
+        let hand = state.player.as_ref()[state.turn as usize];
+        let mut results = hand
+            .follow(&state.lead, &RuleSet::default())
+            .into_iter()
+            .map(|(action, hand)| {
+                let next = state.next(action, hand);
+                if hand.is_empty() {
+                    state.our_turn()
+                } else {
+                    brute_force(&next)
+                }
+            });
+        if state.our_turn() {
+            results.any(|r| r)
+        } else {
+            results.all(|r| r)
+        }
+    }
+
+    #[test]
+    fn test_transposition() {
+        // 3344 与 4433 等不同出牌顺序会到达相同局面
+        let game = Game::new(vec!["334455k", "6789"], 1).unwrap();
+        assert!(game.pass());
+        assert_strategy(&game, game.root);
+        assert!(game.table.is_empty() && game.open.is_empty());
+
+        for (hands, turn, landlord) in [
+            (vec!["3357899k", "34668jq"], 1, 0),
+            (vec!["33445566", "778899"], 0, 0),
+            (vec!["3456", "789t", "jjq"], 0, 1),
+            (vec!["3344a", "556", "2"], 1, 1),
+            (vec!["3399k", "5577", "6"], 2, 0),
+        ] {
+            let state = State::with_landlord(hands, turn, landlord).unwrap();
+            let game = Game::from_state(state.clone());
+            assert_eq!(game.pass(), brute_force(&state), "{}", state);
+            if game.pass() {
+                assert_strategy(&game, game.root);
+            }
+        }
+    }
+
+    #[test]
+    fn test_search_mode() {
+        for (hands, turn, landlord) in [
+            (vec!["123", "234"], 0, 0),
+            (vec!["34", "5"], 0, 0),
+            (vec!["3357899k", "34668jq"], 1, 0),
+            (vec!["33445566", "778899"], 0, 0),
+            (vec!["35", "4", "6"], 0, 1),
+            (vec!["3344a", "556", "2"], 1, 1),
+            (vec!["3399k", "5577", "6"], 2, 0),
+        ] {
+            let state = State::with_landlord(hands, turn, landlord).unwrap();
+            let tree = Game::from_state(state.clone());
+            let search = Game::with_mode(state.clone(), Mode::Search);
+            assert_eq!(tree.pass(), search.pass(), "{}", state);
+            // 置换表很小时频繁清空，结果不变
+            let small = Game::with_config(
+                state.clone(),
+                Config {
+                    mode: Mode::Search,
+                    table_bytes: Some(256),
+                    ..Default::default()
+                },
+            );
+            assert_eq!(small.pass(), search.pass(), "{}", state);
+            if search.pass() {
+                assert_strategy(&search, search.root);
+                assert!(
+                    search.root.descendants(&search.arena).count()
+                        <= tree.root.descendants(&tree.arena).count()
+                );
+            } else {
+                assert_eq!(search.root.descendants(&search.arena).count(), 1);
+            }
+        }
+    }
+
+    /// 朴素的极小化极大，求我方最少回合数
+    fn brute_distance(state: &State) -> Option<u8> {
+        let hand = state.player[state.turn as usize];
+        let distances = hand
+            .follow(&state.lead, &RuleSet::default())
+            .into_iter()
+            .map(|(action, hand)| {
+                if hand.is_empty() {
+                    state.our_turn().then_some(0)
+                } else {
+                    brute_distance(&state.next(action, hand))
+                }
+            });
+        if state.our_turn() {
+            distances.flatten().min().map(|d| d + 1)
+        } else {
+            distances.collect::<Option<Vec<u8>>>()?.into_iter().max()
+        }
+    }
+
+    #[test]
+    fn test_shortest_mode() {
+        for (hands, turn, landlord) in [
+            (vec!["123", "234"], 0, 0),
+            (vec!["33445566", "778899"], 0, 0),
+            (vec!["334455k", "6789"], 1, 0),
+            (vec!["3456789", "t"], 0, 0),
+            (vec!["35", "4", "6"], 0, 1),
+            (vec!["3344a", "556", "2"], 1, 1),
+        ] {
+            let state = State::with_landlord(hands, turn, landlord).unwrap();
+            let tree = Game::from_state(state.clone());
+            let shortest = Game::with_mode(state.clone(), Mode::Shortest);
+            assert_eq!(tree.pass(), shortest.pass(), "{}", state);
+            if shortest.pass() {
+                assert_strategy(&shortest, shortest.root);
+                let turns = shortest.our_turns(shortest.root);
+                assert_eq!(Some(turns), brute_distance(&state), "{}", state);
+                assert!(turns <= tree.our_turns(tree.root));
+            }
+        }
+    }
+
+    #[test]
+    fn test_all_wins() {
+        for (hands, turn, landlord) in [
+            (vec!["33445566", "778899"], 0, 0),
+            (vec!["334455k", "6789"], 0, 0),
+            (vec!["3456789", "t"], 0, 0),
+            (vec!["35", "4", "6"], 0, 1),
+            (vec!["34", "5"], 0, 0),
+        ] {
+            let state = State::with_landlord(hands, turn, landlord).unwrap();
+            // 朴素搜索得到的所有必胜首招
+            let expected = state.player[state.turn as usize]
+                .follow(&state.lead, &RuleSet::default())
+                .into_iter()
+                .filter(|&(action, hand)| hand.is_empty() || brute_force(&state.next(action, hand)))
+                .map(|(action, _)| Vec::<Card>::from(action))
+                .collect::<Vec<Vec<Card>>>();
+
+            for mode in [Mode::Tree, Mode::Search, Mode::Shortest] {
+                let config = Config {
+                    mode,
+                    all_wins: true,
+                    ..Default::default()
+                };
+                let game = Game::with_config(state.clone(), config);
+                assert_eq!(game.pass(), !expected.is_empty(), "{}", state);
+                if game.pass() {
+                    assert_strategy(&game, game.root);
+                }
+                let mut moves = game
+                    .winning_moves(None)
+                    .into_iter()
+                    .map(|(_, cards)| cards)
+                    .collect::<Vec<Vec<Card>>>();
+                let mut expected = expected.clone();
+                moves.sort();
+                expected.sort();
+                assert_eq!(moves, expected, "{:?} {}", mode, state);
+            }
+
+            let game = Game::from_state(state.clone());
+            assert!(game.winning_moves(None).len() <= 1);
+        }
+    }
+
+    #[test]
+    fn test_budget() {
+        let hands = vec!["3357899k", "34668jq"];
+        for mode in [Mode::Tree, Mode::Search, Mode::Shortest] {
+            let state = State::new(hands.clone(), 1).unwrap();
+            let config = Config {
+                mode,
+                max_nodes: Some(10),
+                ..Default::default()
+            };
+            let game = Game::with_config(state.clone(), config);
+            assert_eq!(game.outcome(), Outcome::Unknown, "{:?}", mode);
+            assert!(!game.pass());
+
+            let config = Config {
+                mode,
+                cancel: Some(Arc::new(AtomicBool::new(true))),
+                ..Default::default()
+            };
+            let game = Game::with_config(state.clone(), config);
+            assert_eq!(game.outcome(), Outcome::Unknown, "{:?}", mode);
+
+            let config = Config {
+                mode,
+                timeout: Some(Duration::ZERO),
+                ..Default::default()
+            };
+            let game = Game::with_config(state.clone(), config);
+            assert_eq!(game.outcome(), Outcome::Unknown, "{:?}", mode);
+
+            let game = Game::with_mode(state.clone(), mode);
+            assert_eq!(game.outcome(), Outcome::Win, "{:?}", mode);
+            let game = Game::with_mode(State::new(vec!["34", "5"], 0).unwrap(), mode);
+            assert_eq!(game.outcome(), Outcome::Loss, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_budget_limits_tree() {
+        // 保留所有必胜出法时方案树成指数增长，生成的节点也要受预算限制
+        let state = State::with_landlord(vec!["8899D", "56TJA2", "345T"], 1, 2).unwrap();
+        for mode in [Mode::Tree, Mode::Search, Mode::Shortest] {
+            let config = Config {
+                mode,
+                all_wins: true,
+                max_nodes: Some(20000),
+                ..Default::default()
+            };
+            let game = Game::with_config(state.clone(), config);
+            let stats = game.stats();
+            assert!(stats.created <= 20001, "{:?} {:?}", mode, stats);
+            assert!(game.arena.len() <= 20001, "{:?}", mode);
+            assert_eq!(game.outcome(), Outcome::Unknown, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_all_wins_bounded() {
+        // 必胜出法太多时不再全部保留，不设预算也不会耗尽内存
+        let state = State::with_landlord(vec!["8899D", "56TJA2", "345T"], 1, 2).unwrap();
+        let config = Config {
+            mode: Mode::Search,
+            all_wins: true,
+            ..Default::default()
+        };
+        let game = Game::with_config(state, config);
+        assert_eq!(game.outcome(), Outcome::Win);
+        assert!(
+            game.arena.len() < 2 * ALL_WINS_NODES,
+            "{}",
+            game.arena.len()
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let state = State::new(vec!["3357899k", "34668jq"], 1).unwrap();
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        for mode in [Mode::Tree, Mode::Search, Mode::Shortest] {
+            let counter = calls.clone();
+            let config = Config {
+                mode,
+                progress: Some(Arc::new(move |stats: &Stats| {
+                    assert!(stats.expanded > 0);
+                    counter.fetch_add(1, Ordering::Relaxed);
+                })),
+                ..Default::default()
+            };
+            let game = Game::with_config(state.clone(), config);
+            let stats = game.stats();
+            assert!(stats.expanded > 0, "{:?}", mode);
+            assert!(stats.max_depth > 0, "{:?}", mode);
+            assert!(stats.peak_nodes > 0, "{:?}", mode);
+            assert!(stats.pruned + stats.rolled_back > 0, "{:?}", mode);
+            if mode == Mode::Tree {
+                assert!(stats.peak_nodes >= game.arena.live_count());
+            }
+        }
+        assert!(calls.load(Ordering::Relaxed) >= 3);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel() {
+        for (hands, turn, landlord) in [
+            (vec!["3357899", "4668jq"], 1, 0),
+            (vec!["3357899", "4668jq"], 0, 0),
+            (vec!["33445566", "778899"], 0, 0),
+            (vec!["34", "5"], 0, 0),
+            (vec!["35", "4", "6"], 0, 1),
+            (vec!["3344a", "556", "2"], 1, 1),
+        ] {
+            let state = State::with_landlord(hands, turn, landlord).unwrap();
+            for mode in [Mode::Tree, Mode::Search, Mode::Shortest] {
+                for all_wins in [false, true] {
+                    let config = Config {
+                        mode,
+                        all_wins,
+                        ..Default::default()
+                    };
+                    let sequential = Game::with_config(state.clone(), config.clone());
+                    let parallel = Game::with_config(
+                        state.clone(),
+                        Config {
+                            parallel: true,
+                            ..config
+                        },
+                    );
+                    assert_eq!(sequential.outcome(), parallel.outcome(), "{}", state);
+                    if parallel.pass() {
+                        assert_strategy(&parallel, parallel.root);
+                        assert!(!parallel.action(None).1.is_empty() || state.our_turn());
+                    }
+                    if mode == Mode::Shortest && parallel.pass() {
+                        assert_eq!(
+                            sequential.our_turns(sequential.root),
+                            parallel.our_turns(parallel.root)
+                        );
+                    }
+                    if all_wins {
+                        assert_eq!(
+                            sequential.winning_moves(None).len(),
+                            parallel.winning_moves(None).len()
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// 反驳方案：我方每种出法都在，对方只有一种应对，并且每个局面我方都必败
+    fn assert_refutation(game: &Game, node_id: NodeId) {
+        let state = game.arena[node_id].get();
+        assert!(state.pass, "{}", state);
+        let children = node_id.children(&game.arena).collect::<Vec<NodeId>>();
+        if children.is_empty() {
+            let parent = game.arena[node_id].parent().unwrap();
+            assert!(!game.arena[parent].get().our_turn());
+            return;
+        }
+        assert!(!brute_force(state), "{}", state);
+        if state.our_turn() {
+            let hand = state.player[state.turn as usize];
+            assert_eq!(
+                children.len(),
+                hand.follow(&state.lead, &RuleSet::default()).len()
+            );
+        } else {
+            assert_eq!(children.len(), 1);
+        }
+        for child in children {
+            assert_refutation(game, child);
+        }
+    }
+
+    #[test]
+    fn test_laizi() {
+        // 没有癞子时不论先出哪张，对方都用A出完；7是癞子时可以当3凑成对子一手出完
+        let state = State::new(vec!["37", "a"], 0).unwrap();
+        let config = Config {
+            rules: RuleSet {
+                laizi: Some(Card::Seven),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        for mode in [Mode::Tree, Mode::Search, Mode::Shortest] {
+            assert!(!Game::with_mode(state.clone(), mode).pass());
+            let game = Game::with_config(
+                state.clone(),
+                Config {
+                    mode,
+                    ..config.clone()
+                },
+            );
+            assert!(game.pass(), "{:?}", mode);
+            assert_eq!(game.action(None).0, vec![Card::Three, Card::Three]);
+        }
+
+        // 先出4会被对方用软炸弹管上出完；先出硬炸弹，对方的软炸弹管不上
+        let state = State::new(vec!["33334", "5557"], 0).unwrap();
+        for mode in [Mode::Tree, Mode::Search] {
+            let game = Game::with_config(
+                state.clone(),
+                Config {
+                    mode,
+                    ..config.clone()
+                },
+            );
+            assert!(game.pass(), "{:?}", mode);
+            assert_eq!(game.action(None).0, vec![Card::Three; 4]);
+        }
+    }
+
+    #[test]
+    fn test_two_decks() {
+        // 四人斗地主，地主先出五张的炸弹，农民的四张炸弹管不上；四带二不带同点数的牌，
+        // 否则3333带34一手就出完了
+        let state =
+            State::<DoubleHand>::from_hands(vec!["333334", "2222", "5", "6"], 0, 0).unwrap();
+        let rules = RuleSet {
+            kicker_same_rank: false,
+            ..Default::default()
+        };
+        for mode in [Mode::Tree, Mode::Search] {
+            let config = Config {
+                mode,
+                rules,
+                ..Default::default()
+            };
+            let game = Game::with_config(state.clone(), config);
+            assert!(game.pass(), "{:?}", mode);
+            assert_eq!(game.action(None).0, vec![Card::Three; 5]);
+            assert_eq!(game.pass(), brute_force(&state));
+        }
+
+        // 对方不论怎样出，都用天王炸管上再出完
+        let state = State::<DoubleHand>::from_hands(vec!["XXDD3", "444444445"], 1, 0).unwrap();
+        assert!(Game::from_state(state).pass());
+    }
+
+    #[test]
+    fn test_refutation() {
+        for (hands, turn, landlord) in [
+            (vec!["34", "5"], 0, 0),
+            (vec!["3357899", "4668jq"], 0, 0),
+            (vec!["35", "4", "6"], 0, 0),
+            (vec!["3", "KK", "4"], 1, 1),
+        ] {
+            let state = State::with_landlord(hands, turn, landlord).unwrap();
+            for mode in [Mode::Tree, Mode::Search] {
+                let game = Game::with_mode(state.clone(), mode);
+                if game.pass() {
+                    assert!(game.refutation().is_none());
+                    continue;
+                }
+                let refutation = game.refutation().unwrap();
+                assert!(refutation.is_refutation());
+                assert_eq!(refutation.outcome(), Outcome::Loss);
+                assert!(refutation.winning_moves(None).is_empty());
+                assert_refutation(&refutation, refutation.root);
+            }
+        }
+
+        // 我方先出3或4，对方都用5应对
+        let game = Game::new(vec!["34", "5"], 0).unwrap();
+        let refutation = game.refutation().unwrap();
+        let (reply, ours) = refutation.action(None);
+        assert!(reply.is_empty());
+        assert_eq!(ours.len(), 2);
+        let (reply, _) = refutation.action(Some(ours[0].0));
+        assert_eq!(reply, vec![Card::Five]);
+    }
 }
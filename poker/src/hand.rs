@@ -1,729 +1,1305 @@
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::str::FromStr;
 
 use crate::action::Action;
 use crate::card::{Card, SuitCard};
+use crate::combination::Combination;
 use crate::game::{Carry, StraightType};
+use crate::rules::RuleSet;
+use crate::Error;
 
-/// 用u64表示一副牌，每16位代表一个花色，分别是桃仙梅方；用后15位分别表示大王、小王、2、A、K、Q、J、10、9、8、7、6、5、4、3
+/// 用u64表示一副牌，每16位代表一个花色，从高到低分别是桃心梅方；用后15位分别表示大王、小王、2、A、K、Q、J、10、9、8、7、6、5、4、3
 pub const DECK_OF_CARDS: Hand =
     Hand(0b0001111111111111000111111111111100011111111111110111111111111111);
 
-#[derive(Default, Copy, Clone, Debug, PartialEq)]
+/// 两副牌，四人斗地主用
+pub const DOUBLE_DECK: DoubleHand =
+    DoubleHand(DECK_OF_CARDS.0 as u128 | (DECK_OF_CARDS.0 as u128) << 64);
+
+/// 一副牌中的手牌
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Hand(u64);
 
-impl From<&str> for Hand {
-    fn from(value: &str) -> Self {
-        let mut hand = Hand(0);
-        for c in value.chars() {
-            match Card::from_char(c) {
-                Ok(card) => hand.draw_card(card),
-                Err(e) => log::error!("{}", e),
-            }
-        }
-        hand
+/// 两副牌中的手牌，低64位是第一副，高64位是第二副，每副的排列与Hand相同
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DoubleHand(u128);
+
+pub(crate) mod sealed {
+    use crate::action::Action;
+    use crate::card::{Card, SuitCard};
+    use crate::rules::RuleSet;
+
+    /// 求解用到的手牌操作，不对外公开
+    pub trait Sealed: Sized {
+        fn follow(&self, action: &Action, rules: &RuleSet) -> Vec<(Action, Self)>;
+        fn arrange(&self) -> Self;
+        fn card_count(&self, card: Card) -> u32;
+        fn draw_card(&mut self, card: Card);
+        fn play_card(&mut self, card: Card) -> Option<SuitCard>;
     }
 }
 
-impl Hand {
-    pub fn value(&self) -> u64 {
-        self.0
-    }
+/// 能求解的手牌：一副牌用Hand，两副牌用DoubleHand
+pub trait Cards:
+    sealed::Sealed
+    + Copy
+    + Default
+    + Eq
+    + Hash
+    + Debug
+    + Display
+    + FromStr<Err = Error>
+    + for<'a> From<&'a str>
+    + Send
+    + Sync
+    + 'static
+{
+    /// 几副牌
+    const DECKS: u8;
+    /// 最多几个玩家，一副牌最多三人，两副牌最多四人
+    const MAX_PLAYERS: usize;
+    /// 各玩家的手牌，用定长数组保存，避免每个节点单独分配内存
+    type Hands: Copy + Default + Eq + Hash + Debug + Send + Sync + AsRef<[Self]> + AsMut<[Self]>;
+
+    fn size(&self) -> u32;
+    fn is_empty(&self) -> bool;
+}
 
-    /// 手牌张数
-    pub fn size(&self) -> u32 {
-        self.0.count_ones()
-    }
+/// Hand和DoubleHand的实现只有位宽和牌的副数不同
+macro_rules! hand {
+    ($hand:ident, $bits:ty, $decks:literal, $players:literal) => {
+        impl From<&str> for $hand {
+            /// 宽松解析，不认识的字符记录日志后忽略，超出的牌丢弃；检查输入用parse
+            fn from(value: &str) -> Self {
+                let mut hand = $hand(0);
+                for c in value.chars() {
+                    match Card::from_char(c) {
+                        Ok(card) => hand.draw_card(card),
+                        Err(e) => log::error!("{}", e),
+                    }
+                }
+                hand
+            }
+        }
 
-    pub fn is_empty(&self) -> bool {
-        self.0 == 0
-    }
+        impl FromStr for $hand {
+            type Err = Error;
+
+            /// 严格解析手牌：有不认识的字符、某个点数超过四张、大王或小王超过一张、
+            /// 同一张带花色的牌超过一张（两副牌时都加倍）、没有牌时返回错误。
+            /// 接受Display输出的两种写法：不带花色的"3345XD"，或带花色、用空白分隔的"♠3 ♥3 ♦4 ♣5 X D"
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let mut hand = $hand::default();
+                if SuitCard::is_suit_notation(s) {
+                    let chars = s.chars().collect::<Vec<char>>();
+                    let mut i = 0;
+                    while i < chars.len() {
+                        if chars[i].is_whitespace() {
+                            i += 1;
+                            continue;
+                        }
+                        let start = i;
+                        while i < chars.len() && !chars[i].is_whitespace() {
+                            i += 1;
+                        }
+                        let token = &chars[start..i];
+                        let suit_card = token
+                            .iter()
+                            .collect::<String>()
+                            .parse::<SuitCard>()
+                            .map_err(|e| {
+                                if let Error::SuitedJoker(card, _) = e {
+                                    return Error::SuitedJoker(card, start);
+                                }
+                                // 指出第一个出错的字符
+                                let bad = match token.len() {
+                                    1 => start,
+                                    2 if SuitCard::suit_from_char(token[0]).is_none() => start,
+                                    2 => start + 1,
+                                    _ => start + 2,
+                                };
+                                Error::UnknownCard(chars[bad], bad)
+                            })?;
+                        hand.check_count(suit_card.card())?;
+                        if suit_card.card() < Card::BlackJoker && hand.copies(suit_card) >= $decks {
+                            return Err(Error::DuplicateCard(suit_card));
+                        }
+                        hand.insert_suit_card(suit_card);
+                    }
+                } else {
+                    for (i, c) in s.chars().enumerate() {
+                        if c.is_whitespace() {
+                            continue;
+                        }
+                        let card = Card::from_char(c).map_err(|_| Error::UnknownCard(c, i))?;
+                        hand.check_count(card)?;
+                        hand.draw_card(card);
+                    }
+                }
+                if hand.is_empty() {
+                    return Err(Error::Empty);
+                }
+                Ok(hand)
+            }
+        }
 
-    pub fn contains(&self, suit_card: SuitCard) -> bool {
-        let c = u64::from(suit_card);
-        self.0 & c == c
-    }
+        impl $hand {
+            /// 再加一张card是否超出牌的张数
+            fn check_count(&self, card: Card) -> Result<(), Error> {
+                let count = self.card_count(card) + 1;
+                if card >= Card::BlackJoker && count > $decks {
+                    return Err(Error::TooManyJokers(card, $decks));
+                }
+                if count > 4 * $decks {
+                    return Err(Error::TooManyOfRank(card, 4 * $decks));
+                }
+                Ok(())
+            }
 
-    pub fn insert_suit_card(&mut self, suit_card: SuitCard) {
-        self.0 |= u64::from(suit_card);
-    }
+            /// 一张牌在第deck副牌中的位
+            fn bit(suit_card: SuitCard, deck: u32) -> $bits {
+                <$bits>::from(u64::from(suit_card)) << (64 * deck)
+            }
 
-    pub fn remove_suit_card(&mut self, suit_card: SuitCard) {
-        self.0 &= !u64::from(suit_card)
-    }
+            /// 同一张带花色的牌有几张
+            fn copies(&self, suit_card: SuitCard) -> u32 {
+                (0..$decks)
+                    .filter(|&deck| self.0 & Self::bit(suit_card, deck) != 0)
+                    .count() as u32
+            }
 
-    pub fn insert_hand(&mut self, hand: Hand) {
-        self.0 |= hand.0
-    }
+            /// 所有的牌，按点数从小到大，同点数先第一副再第二副、花色按桃心梅方
+            pub fn suit_cards(&self) -> Vec<SuitCard> {
+                let mut cards = Vec::new();
+                let mut card = Some(Card::Three);
+                while let Some(c) = card {
+                    for deck in 0..$decks {
+                        for suit in (0..4).rev() {
+                            if self.0 & Self::bit(SuitCard::new(c, suit), deck) != 0 {
+                                cards.push(SuitCard::new(c, suit));
+                            }
+                        }
+                    }
+                    card = c.plus();
+                }
+                cards
+            }
 
-    pub fn remove_hand(&mut self, hand: Hand) {
-        self.0 &= !hand.0
-    }
-}
+            pub fn value(&self) -> $bits {
+                self.0
+            }
 
-impl Hand {
-    /// 无视花色的手牌整理，高位的1跟低位的0互换
-    pub(crate) fn arrange(&self) -> Hand {
-        let mut segments = [
-            self.0 & 0xFFFF,
-            (self.0 >> 16) & 0xFFFF,
-            (self.0 >> 32) & 0xFFFF,
-            (self.0 >> 48) & 0xFFFF,
-        ];
-
-        for i in (1..4).rev() {
-            for j in 0..i {
-                if segments[i] == 0 {
-                    break;
-                }
-
-                //相同的位是0，不同的位是1
-                let different = segments[i] ^ segments[j];
-                //相同的位是1，不同的位是0
-                let identical = !different;
-                // 相同的位保持不变，不同的位设为0
-                segments[i] &= identical;
-                // 相同的位保持不变，不同的位设为1
-                segments[j] |= different;
+            /// 手牌张数
+            pub fn size(&self) -> u32 {
+                self.0.count_ones()
             }
-        }
 
-        Hand(segments.iter().rev().fold(0, |v, s| s | (v << 16)))
-    }
+            pub fn is_empty(&self) -> bool {
+                self.0 == 0
+            }
 
-    /// 抓牌，不考虑花色，优先放在低位
-    fn draw_card(&mut self, card: Card) {
-        let mut card = card as u64;
-        for _ in 0..4 {
-            if self.0 & card == 0 {
-                self.0 |= card;
-                break;
+            /// 任意一副牌中有这张牌
+            pub fn contains(&self, suit_card: SuitCard) -> bool {
+                (0..$decks).any(|deck| {
+                    let c = Self::bit(suit_card, deck);
+                    self.0 & c == c
+                })
             }
-            card <<= 16;
-        }
-    }
 
-    /// 打出一张牌
-    pub fn play_card(&mut self, card: Card) -> Option<SuitCard> {
-        for i in (0..4).rev() {
-            let suit_card = SuitCard::new(card, i);
-            let c = u64::from(suit_card);
-            if self.0 & c == c {
-                self.0 &= !c;
-                return Some(suit_card);
+            /// 放入还没有这张牌的第一副；大小王没有花色，与抓牌相同
+            pub fn insert_suit_card(&mut self, suit_card: SuitCard) {
+                if suit_card.card() >= Card::BlackJoker {
+                    self.draw_card(suit_card.card());
+                } else if let Some(c) = (0..$decks)
+                    .map(|deck| Self::bit(suit_card, deck))
+                    .find(|&c| self.0 & c == 0)
+                {
+                    self.0 |= c;
+                }
             }
-        }
-        None
-    }
 
-    /// “管上”指定牌组的所有方案
-    pub(crate) fn follow(&self, action: &Action) -> Vec<(Action, Hand)> {
-        let mut own_round = false;
-        let mut not_bomb = true;
-        let mut actions = match action {
-            Action::None => {
-                own_round = true;
-                self.follow_any()
-            }
-            Action::Single(card) => self.follow_single(Some(card)),
-            Action::Sequence5(c) => self.follow_straight(StraightType::Single, Some(c), 5),
-            Action::Sequence6(c) => self.follow_straight(StraightType::Single, Some(c), 6),
-            Action::Sequence7(c) => self.follow_straight(StraightType::Single, Some(c), 7),
-            Action::Sequence8(c) => self.follow_straight(StraightType::Single, Some(c), 8),
-            Action::Sequence9(c) => self.follow_straight(StraightType::Single, Some(c), 9),
-            Action::Sequence10(c) => self.follow_straight(StraightType::Single, Some(c), 10),
-            Action::Sequence11(c) => self.follow_straight(StraightType::Single, Some(c), 11),
-            Action::Sequence12 => Vec::new(),
-            Action::Pair(c) => self.follow_pair(Some(c)),
-            Action::PairSequence3(c) => self.follow_straight(StraightType::Pair, Some(c), 3),
-            Action::PairSequence4(c) => self.follow_straight(StraightType::Pair, Some(c), 4),
-            Action::PairSequence5(c) => self.follow_straight(StraightType::Pair, Some(c), 5),
-            Action::PairSequence6(c) => self.follow_straight(StraightType::Pair, Some(c), 6),
-            Action::PairSequence7(c) => self.follow_straight(StraightType::Pair, Some(c), 7),
-            Action::PairSequence8(c) => self.follow_straight(StraightType::Pair, Some(c), 8),
-            Action::PairSequence9(c) => self.follow_straight(StraightType::Pair, Some(c), 9),
-            Action::PairSequence10(c) => self.follow_straight(StraightType::Pair, Some(c), 10),
-            Action::Triplet(c) => self.follow_triple(Some(c), Carry::None),
-            Action::TripletSingle(c, _) => self.follow_triple(Some(c), Carry::Single),
-            Action::TripletPair(c, _) => self.follow_triple(Some(c), Carry::Pair),
-            Action::TripletSequence2(c) => self.follow_straight(StraightType::Triple, Some(c), 2),
-            Action::TripletSequence2Single(c, _, _) => {
-                self.follow_triple_straight(Some(c), Carry::Single, 2)
-            }
-            Action::TripletSequence2Pair(c, _, _) => {
-                self.follow_triple_straight(Some(c), Carry::Pair, 2)
-            }
-            Action::TripletSequence3(c) => self.follow_straight(StraightType::Triple, Some(c), 3),
-            Action::TripletSequence3Single(c, ..) => {
-                self.follow_triple_straight(Some(c), Carry::Single, 3)
-            }
-            Action::TripletSequence3Pair(c, _, _, _) => {
-                self.follow_triple_straight(Some(c), Carry::Pair, 3)
-            }
-            Action::TripletSequence4(c) => self.follow_straight(StraightType::Triple, Some(c), 4),
-            Action::TripletSequence4Single(c, _, _, _, _) => {
-                self.follow_triple_straight(Some(c), Carry::Single, 4)
-            }
-            Action::TripletSequence4Pair(c, _, _, _, _) => {
-                self.follow_triple_straight(Some(c), Carry::Pair, 4)
-            }
-            Action::TripletSequence5(c) => self.follow_straight(StraightType::Triple, Some(c), 5),
-            Action::TripletSequence5Single(c, _, _, _, _, _) => {
-                self.follow_triple_straight(Some(c), Carry::Single, 5)
-            }
-            Action::TripletSequence6(c) => self.follow_straight(StraightType::Triple, Some(c), 6),
-            Action::QuadSingle(c, _, _) => self.follow_bomb_carry(Some(c), Carry::Single),
-            Action::QuadPair(c, _, _) => self.follow_bomb_carry(Some(c), Carry::Pair),
-            Action::Bomb(c) => {
-                not_bomb = false;
-                self.follow_bomb(Some(c))
-            }
-            Action::Rocket => {
-                not_bomb = false;
-                Vec::new()
+            /// 优先移除后一副牌里的这张牌
+            pub fn remove_suit_card(&mut self, suit_card: SuitCard) {
+                if suit_card.card() >= Card::BlackJoker {
+                    self.play_card(suit_card.card());
+                } else if let Some(c) = (0..$decks)
+                    .rev()
+                    .map(|deck| Self::bit(suit_card, deck))
+                    .find(|&c| self.0 & c != 0)
+                {
+                    self.0 &= !c;
+                }
             }
-        };
 
-        if not_bomb {
-            actions.extend_from_slice(&self.follow_bomb(None));
-        }
+            pub fn insert_hand(&mut self, hand: $hand) {
+                self.0 |= hand.0
+            }
+
+            pub fn remove_hand(&mut self, hand: $hand) {
+                self.0 &= !hand.0
+            }
 
-        if !own_round {
-            actions.push((Action::None, *self));
+            /// 所有合法的出牌和出牌后剩下的手牌，to_beat为None时是自由出牌，“不要”用None表示。
+            /// 顺序固定，与求解时尝试的顺序相同：
+            /// 自由出牌时大致按张数从多到少排列牌型，跟牌时先是同类牌型，然后是炸弹、火箭，最后是“不要”；
+            /// 同类牌型中点数从小到大。剩下的手牌保留原来的花色，同点数的牌先出花色大的
+            pub fn legal_moves(
+                &self,
+                to_beat: Option<&Combination>,
+            ) -> Vec<(Option<Combination>, $hand)> {
+                self.legal_moves_with(to_beat, &RuleSet::default())
+            }
+
+            /// 按指定规则的legal_moves。有癞子时，用了癞子当替身的出法排在不用的之后、“不要”之前；
+            /// 牌型中是癞子代表的点数，剩下的手牌中少的是实际出的癞子
+            pub fn legal_moves_with(
+                &self,
+                to_beat: Option<&Combination>,
+                rules: &RuleSet,
+            ) -> Vec<(Option<Combination>, $hand)> {
+                let lead = to_beat.map_or(Action::None, |c| c.0);
+                self.arrange()
+                    .follow(&lead, rules)
+                    .into_iter()
+                    .map(|(action, rest)| {
+                        let mut hand = *self;
+                        let mut card = Some(Card::Three);
+                        while let Some(c) = card {
+                            for _ in rest.card_count(c)..self.card_count(c) {
+                                hand.play_card(c);
+                            }
+                            card = c.plus();
+                        }
+                        (
+                            (action != Action::None).then_some(Combination(action)),
+                            hand,
+                        )
+                    })
+                    .collect()
+            }
         }
-        actions
-    }
 
-    fn follow_any(&self) -> Vec<(Action, Hand)> {
-        let mut actions = Vec::new();
-        //20张牌
-        actions.extend_from_slice(&self.follow_triple_straight(None, Carry::Single, 5));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 10));
-        actions.extend_from_slice(&self.follow_triple_straight(None, Carry::Pair, 4));
-        //18张牌
-        actions.extend_from_slice(&self.follow_straight(StraightType::Triple, None, 6));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 9));
-        //16张牌
-        actions.extend_from_slice(&self.follow_triple_straight(None, Carry::Single, 4));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 8));
-        //15张牌
-        actions.extend_from_slice(&self.follow_triple_straight(None, Carry::Pair, 3));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Triple, None, 5));
-        //14张牌
-        actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 7));
-        //12张牌
-        actions.extend_from_slice(&self.follow_triple_straight(None, Carry::Single, 3));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Triple, None, 4));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 6));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Single, None, 12));
-        //11张牌
-        actions.extend_from_slice(&self.follow_straight(StraightType::Single, None, 11));
-        //10张牌
-        actions.extend_from_slice(&self.follow_triple_straight(None, Carry::Pair, 2));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 5));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Single, None, 10));
-        //9张牌
-        actions.extend_from_slice(&self.follow_straight(StraightType::Triple, None, 3));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Single, None, 9));
-        //8张牌
-        actions.extend_from_slice(&self.follow_triple_straight(None, Carry::Single, 2));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 4));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Single, None, 8));
-        //7张牌
-        actions.extend_from_slice(&self.follow_straight(StraightType::Single, None, 7));
-        //6张牌
-        actions.extend_from_slice(&self.follow_straight(StraightType::Triple, None, 2));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 3));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Single, None, 6));
-        //5张牌
-        actions.extend_from_slice(&self.follow_triple(None, Carry::Pair));
-        actions.extend_from_slice(&self.follow_straight(StraightType::Single, None, 5));
-        //3张牌
-        actions.extend_from_slice(&self.follow_triple(None, Carry::None));
-        //4张牌
-        actions.extend_from_slice(&self.follow_triple(None, Carry::Single));
-        //2张牌
-        actions.extend_from_slice(&self.follow_pair(None));
-        //1张牌
-        actions.extend_from_slice(&self.follow_single(None));
-        //8张牌
-        actions.extend_from_slice(&self.follow_bomb_carry(None, Carry::Pair));
-        //6张牌
-        actions.extend_from_slice(&self.follow_bomb_carry(None, Carry::Single));
-        actions
-    }
+        impl $hand {
+            /// 无视花色的手牌整理，高位的1跟低位的0互换
+            pub(crate) fn arrange(&self) -> $hand {
+                let mut segments = [0; 4 * $decks];
+                for (i, segment) in segments.iter_mut().enumerate() {
+                    *segment = (self.0 >> (16 * i)) & 0xFFFF;
+                }
 
-    fn follow_single(&self, card: Option<&Card>) -> Vec<(Action, Hand)> {
-        let mut actions = Vec::new();
-        let mut card = Self::plus(card);
+                for i in (1..4 * $decks).rev() {
+                    for j in 0..i {
+                        if segments[i] == 0 {
+                            break;
+                        }
 
-        loop {
-            let Some(c) = card else {
-                return actions;
-            };
+                        //相同的位是0，不同的位是1
+                        let different = segments[i] ^ segments[j];
+                        //相同的位是1，不同的位是0
+                        let identical = !different;
+                        // 相同的位保持不变，不同的位设为0
+                        segments[i] &= identical;
+                        // 相同的位保持不变，不同的位设为1
+                        segments[j] |= different;
+                    }
+                }
 
-            let mut hand = *self;
-            if hand.play_card(c).is_some() {
-                actions.push((Action::Single(c), hand));
+                $hand(segments.iter().rev().fold(0, |v, s| s | (v << 16)))
             }
-            card = c.plus();
-        }
-    }
 
-    fn follow_straight(
-        &self,
-        st: StraightType,
-        card: Option<&Card>,
-        length: u8,
-    ) -> Vec<(Action, Hand)> {
-        let mut actions = Vec::new();
-        let mut card = Self::plus(card);
-        let mut straight = 0_u16;
-        let mut straight_start = card.unwrap();
-
-        for _ in 0..length {
-            let Some(c) = card else { return actions };
-            straight |= c as u16;
-            card = c.plus();
-        }
+            /// 抓牌，不考虑花色，优先放在低位
+            pub(crate) fn draw_card(&mut self, card: Card) {
+                let mut card = card as $bits;
+                for _ in 0..4 * $decks {
+                    if self.0 & card == 0 {
+                        self.0 |= card;
+                        break;
+                    }
+                    card <<= 16;
+                }
+            }
 
-        while straight < Card::Two as u16 {
-            let action = match st {
-                StraightType::Single => self.follow_strait_single(straight, straight_start, length),
-                StraightType::Pair => {
-                    self.follow_straight_pair(straight as u64, straight_start, length)
+            /// 某个点数的张数，不考虑花色
+            pub(crate) fn card_count(&self, card: Card) -> u32 {
+                (0..4 * $decks)
+                    .filter(|i| (self.0 >> (16 * i)) & card as $bits != 0)
+                    .count() as u32
+            }
+
+            /// 打出一张牌，先出第二副牌的
+            pub fn play_card(&mut self, card: Card) -> Option<SuitCard> {
+                for i in (0..4 * $decks).rev() {
+                    let suit_card = SuitCard::new(card, i % 4);
+                    let c = Self::bit(suit_card, u32::from(i / 4));
+                    if self.0 & c == c {
+                        self.0 &= !c;
+                        return Some(suit_card);
+                    }
                 }
-                StraightType::Triple => {
-                    self.follow_straight_triple(straight as u64, straight_start, length)
+                None
+            }
+
+            /// “管上”指定牌组的所有方案。有癞子时，先列出不用癞子当替身的出法，再列出用了的
+            pub(crate) fn follow(&self, action: &Action, rules: &RuleSet) -> Vec<(Action, $hand)> {
+                let mut actions = self.follow_natural(action, rules);
+                if let Some(laizi) = rules.laizi {
+                    if self.card_count(laizi) > 0 {
+                        // 癞子替成带的牌时和本来的出法只差在带的牌，剩下的手牌也相同时是重复的
+                        for (a, rest) in self.follow_laizi(action, rules, laizi) {
+                            if !actions
+                                .iter()
+                                .any(|(b, r)| *r == rest && a.same_but_kickers(b))
+                            {
+                                actions.push((a, rest));
+                            }
+                        }
+                    }
                 }
-            };
-            actions.extend_from_slice(&action);
-            straight_start = straight_start.plus().unwrap();
-            straight <<= 1;
-        }
+                if *action != Action::None {
+                    actions.push((Action::None, *self));
+                }
+                actions
+            }
 
-        actions
-    }
+            /// 每张牌都按本来的点数出，不包括“不要”
+            fn follow_natural(&self, action: &Action, rules: &RuleSet) -> Vec<(Action, $hand)> {
+                let mut actions = match action {
+                    Action::None => self.follow_any(rules),
+                    Action::Single(card) => self.follow_single(Some(card)),
+                    Action::Sequence5(c) => self.follow_sequence(Some(c), 5, rules),
+                    Action::Sequence6(c) => self.follow_sequence(Some(c), 6, rules),
+                    Action::Sequence7(c) => self.follow_sequence(Some(c), 7, rules),
+                    Action::Sequence8(c) => self.follow_sequence(Some(c), 8, rules),
+                    Action::Sequence9(c) => self.follow_sequence(Some(c), 9, rules),
+                    Action::Sequence10(c) => self.follow_sequence(Some(c), 10, rules),
+                    Action::Sequence11(c) => self.follow_sequence(Some(c), 11, rules),
+                    Action::Sequence12 => Vec::new(),
+                    Action::Pair(c) => self.follow_pair(Some(c)),
+                    Action::PairSequence3(c) => {
+                        self.follow_straight(StraightType::Pair, Some(c), 3)
+                    }
+                    Action::PairSequence4(c) => {
+                        self.follow_straight(StraightType::Pair, Some(c), 4)
+                    }
+                    Action::PairSequence5(c) => {
+                        self.follow_straight(StraightType::Pair, Some(c), 5)
+                    }
+                    Action::PairSequence6(c) => {
+                        self.follow_straight(StraightType::Pair, Some(c), 6)
+                    }
+                    Action::PairSequence7(c) => {
+                        self.follow_straight(StraightType::Pair, Some(c), 7)
+                    }
+                    Action::PairSequence8(c) => {
+                        self.follow_straight(StraightType::Pair, Some(c), 8)
+                    }
+                    Action::PairSequence9(c) => {
+                        self.follow_straight(StraightType::Pair, Some(c), 9)
+                    }
+                    Action::PairSequence10(c) => {
+                        self.follow_straight(StraightType::Pair, Some(c), 10)
+                    }
+                    Action::Triplet(c) => self.follow_triple(Some(c), Carry::None),
+                    Action::TripletSingle(c, _) => self.follow_triple(Some(c), Carry::Single),
+                    Action::TripletPair(c, _) => self.follow_triple(Some(c), Carry::Pair),
+                    Action::TripletSequence2(c) => {
+                        self.follow_straight(StraightType::Triple, Some(c), 2)
+                    }
+                    Action::TripletSequence2Single(c, _, _) => {
+                        self.follow_triple_straight(Some(c), Carry::Single, 2, rules)
+                    }
+                    Action::TripletSequence2Pair(c, _, _) => {
+                        self.follow_triple_straight(Some(c), Carry::Pair, 2, rules)
+                    }
+                    Action::TripletSequence3(c) => {
+                        self.follow_straight(StraightType::Triple, Some(c), 3)
+                    }
+                    Action::TripletSequence3Single(c, ..) => {
+                        self.follow_triple_straight(Some(c), Carry::Single, 3, rules)
+                    }
+                    Action::TripletSequence3Pair(c, _, _, _) => {
+                        self.follow_triple_straight(Some(c), Carry::Pair, 3, rules)
+                    }
+                    Action::TripletSequence4(c) => {
+                        self.follow_straight(StraightType::Triple, Some(c), 4)
+                    }
+                    Action::TripletSequence4Single(c, _, _, _, _) => {
+                        self.follow_triple_straight(Some(c), Carry::Single, 4, rules)
+                    }
+                    Action::TripletSequence4Pair(c, _, _, _, _) => {
+                        self.follow_triple_straight(Some(c), Carry::Pair, 4, rules)
+                    }
+                    Action::TripletSequence5(c) => {
+                        self.follow_straight(StraightType::Triple, Some(c), 5)
+                    }
+                    Action::TripletSequence5Single(c, _, _, _, _, _) => {
+                        self.follow_triple_straight(Some(c), Carry::Single, 5, rules)
+                    }
+                    Action::TripletSequence6(c) => {
+                        self.follow_straight(StraightType::Triple, Some(c), 6)
+                    }
+                    Action::QuadSingle(c, _, _) => {
+                        self.follow_bomb_carry(Some(c), Carry::Single, rules)
+                    }
+                    Action::QuadPair(c, _, _) => {
+                        self.follow_bomb_carry(Some(c), Carry::Pair, rules)
+                    }
+                    // 火箭带牌只能用炸弹管
+                    Action::RocketSingle(..) | Action::RocketPair(..) => Vec::new(),
+                    // 炸弹只能用更大的炸弹管，更大的软炸弹由follow_laizi生成
+                    Action::SoftBomb(_)
+                    | Action::SoftBomb5(_)
+                    | Action::SoftBomb6(_)
+                    | Action::SoftBomb7(_)
+                    | Action::SoftBomb8(_)
+                    | Action::Bomb(_)
+                    | Action::Bomb5(_)
+                    | Action::Bomb6(_)
+                    | Action::Bomb7(_)
+                    | Action::Bomb8(_)
+                    | Action::Rocket
+                    | Action::KingBomb => Vec::new(),
+                };
+
+                actions.extend_from_slice(&self.follow_bombs(action.bomb_order()));
+                actions
+            }
 
-    fn follow_strait_single(
-        &self,
-        straight: u16,
-        straight_start: Card,
-        length: u8,
-    ) -> Vec<(Action, Hand)> {
-        let mut actions = Vec::new();
-        let mut hand = *self;
-
-        if hand.0 as u16 & straight == straight {
-            let mut card = straight_start;
-            for _ in 0..length {
-                hand.play_card(card);
-                card = card.plus().unwrap();
-            }
-            match length {
-                5 => actions.push((Action::Sequence5(straight_start), hand)),
-                6 => actions.push((Action::Sequence6(straight_start), hand)),
-                7 => actions.push((Action::Sequence7(straight_start), hand)),
-                8 => actions.push((Action::Sequence8(straight_start), hand)),
-                9 => actions.push((Action::Sequence9(straight_start), hand)),
-                10 => actions.push((Action::Sequence10(straight_start), hand)),
-                11 => actions.push((Action::Sequence11(straight_start), hand)),
-                12 => actions.push((Action::Sequence12, hand)),
-                _ => unreachable!(),
+            /// 癞子当作其他点数的出法：依次把一到全部癞子换成各种点数的替身，按本来的点数出牌，
+            /// 只保留用上了所有替身的出法，避免与用更少替身的出法重复；全是癞子的牌按本来的点数出。
+            /// 用了替身的炸弹是软炸弹
+            fn follow_laizi(
+                &self,
+                action: &Action,
+                rules: &RuleSet,
+                laizi: Card,
+            ) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                let mut substitutes = Vec::new();
+                let wilds = self.card_count(laizi);
+                self.substitute(
+                    action,
+                    rules,
+                    laizi,
+                    wilds,
+                    Card::Three,
+                    &mut substitutes,
+                    &mut actions,
+                );
+                actions
             }
-        }
-        actions
-    }
 
-    fn follow_straight_pair(
-        &self,
-        straight: u64,
-        straight_start: Card,
-        length: u8,
-    ) -> Vec<(Action, Hand)> {
-        let mut actions = Vec::new();
-        let mut hand = *self;
-        let straight = straight | straight << 16;
-
-        if hand.0 & straight == straight {
-            let mut card = straight_start;
-            for _ in 0..length {
-                hand.play_card(card);
-                hand.play_card(card);
-                card = card.plus().unwrap();
-            }
-            match length {
-                3 => actions.push((Action::PairSequence3(straight_start), hand)),
-                4 => actions.push((Action::PairSequence4(straight_start), hand)),
-                5 => actions.push((Action::PairSequence5(straight_start), hand)),
-                6 => actions.push((Action::PairSequence6(straight_start), hand)),
-                7 => actions.push((Action::PairSequence7(straight_start), hand)),
-                8 => actions.push((Action::PairSequence8(straight_start), hand)),
-                9 => actions.push((Action::PairSequence9(straight_start), hand)),
-                10 => actions.push((Action::PairSequence10(straight_start), hand)),
-                _ => unreachable!(),
+            /// 从点数from开始选下一个替身，最多还能选left个
+            #[allow(clippy::too_many_arguments)]
+            fn substitute(
+                &self,
+                action: &Action,
+                rules: &RuleSet,
+                laizi: Card,
+                left: u32,
+                from: Card,
+                substitutes: &mut Vec<Card>,
+                actions: &mut Vec<(Action, $hand)>,
+            ) {
+                if left == 0 {
+                    return;
+                }
+                let mut card = Some(from);
+                // 癞子不能当大小王
+                while let Some(c) = card.filter(|&c| c < Card::BlackJoker) {
+                    card = c.plus();
+                    if c == laizi {
+                        continue;
+                    }
+                    substitutes.push(c);
+                    self.follow_substitutes(action, rules, laizi, substitutes, actions);
+                    self.substitute(action, rules, laizi, left - 1, c, substitutes, actions);
+                    substitutes.pop();
+                }
             }
-        }
-        actions
-    }
 
-    fn follow_straight_triple(
-        &self,
-        straight: u64,
-        straight_start: Card,
-        length: u8,
-    ) -> Vec<(Action, Hand)> {
-        let mut actions = Vec::new();
-        let mut hand = *self;
-        let straight = straight | straight << 16 | straight << 32;
-
-        if hand.0 & straight == straight {
-            let mut card = straight_start;
-            for _ in 0..length {
-                hand.play_card(card);
-                hand.play_card(card);
-                hand.play_card(card);
-                card = card.plus().unwrap();
-            }
-            match length {
-                2 => actions.push((Action::TripletSequence2(straight_start), hand)),
-                3 => actions.push((Action::TripletSequence3(straight_start), hand)),
-                4 => actions.push((Action::TripletSequence4(straight_start), hand)),
-                5 => actions.push((Action::TripletSequence5(straight_start), hand)),
-                6 => actions.push((Action::TripletSequence6(straight_start), hand)),
-                _ => unreachable!(),
+            fn follow_substitutes(
+                &self,
+                action: &Action,
+                rules: &RuleSet,
+                laizi: Card,
+                substitutes: &[Card],
+                actions: &mut Vec<(Action, $hand)>,
+            ) {
+                let mut hand = *self;
+                for &c in substitutes {
+                    hand.play_card(laizi);
+                    if hand.card_count(c) == 4 * $decks {
+                        return;
+                    }
+                    hand.draw_card(c);
+                }
+                let hand = hand.arrange();
+
+                for (played, rest) in hand.follow_natural(action, rules) {
+                    let cards = Vec::<Card>::from(played);
+                    let used_all = substitutes.iter().all(|s| {
+                        let needed = substitutes.iter().filter(|&c| c == s).count();
+                        cards.iter().filter(|&c| c == s).count() >= needed
+                    });
+                    // 全是癞子的牌只能按本来的点数出
+                    if !used_all || cards.len() == substitutes.len() {
+                        continue;
+                    }
+                    let played = match played {
+                        Action::Bomb(c) => Action::SoftBomb(c),
+                        Action::Bomb5(c) => Action::SoftBomb5(c),
+                        Action::Bomb6(c) => Action::SoftBomb6(c),
+                        Action::Bomb7(c) => Action::SoftBomb7(c),
+                        Action::Bomb8(c) => Action::SoftBomb8(c),
+                        _ => played,
+                    };
+                    if action.bomb_order().is_some() && played.bomb_order() <= action.bomb_order() {
+                        continue;
+                    }
+                    actions.push((played, rest));
+                }
             }
-        }
-        actions
-    }
 
-    fn follow_pair(&self, card: Option<&Card>) -> Vec<(Action, Hand)> {
-        let mut actions = Vec::new();
-        let mut card = Self::plus(card);
+            /// 自由出牌。一副牌时一手牌最多20张，所以飞机最多五连带单、四连带对，六连飞机不能带牌；
+            /// 两副牌时也按这个上限生成，连对最多十连
+            fn follow_any(&self, rules: &RuleSet) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                //20张牌
+                actions.extend_from_slice(&self.follow_triple_straight(
+                    None,
+                    Carry::Single,
+                    5,
+                    rules,
+                ));
+                actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 10));
+                actions.extend_from_slice(&self.follow_triple_straight(
+                    None,
+                    Carry::Pair,
+                    4,
+                    rules,
+                ));
+                //18张牌
+                actions.extend_from_slice(&self.follow_straight(StraightType::Triple, None, 6));
+                actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 9));
+                //16张牌
+                actions.extend_from_slice(&self.follow_triple_straight(
+                    None,
+                    Carry::Single,
+                    4,
+                    rules,
+                ));
+                actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 8));
+                //15张牌
+                actions.extend_from_slice(&self.follow_triple_straight(
+                    None,
+                    Carry::Pair,
+                    3,
+                    rules,
+                ));
+                actions.extend_from_slice(&self.follow_straight(StraightType::Triple, None, 5));
+                //14张牌
+                actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 7));
+                //12张牌
+                actions.extend_from_slice(&self.follow_triple_straight(
+                    None,
+                    Carry::Single,
+                    3,
+                    rules,
+                ));
+                actions.extend_from_slice(&self.follow_straight(StraightType::Triple, None, 4));
+                actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 6));
+                actions.extend_from_slice(&self.follow_sequence(None, 12, rules));
+                //11张牌
+                actions.extend_from_slice(&self.follow_sequence(None, 11, rules));
+                //10张牌
+                actions.extend_from_slice(&self.follow_triple_straight(
+                    None,
+                    Carry::Pair,
+                    2,
+                    rules,
+                ));
+                actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 5));
+                actions.extend_from_slice(&self.follow_sequence(None, 10, rules));
+                //9张牌
+                actions.extend_from_slice(&self.follow_straight(StraightType::Triple, None, 3));
+                actions.extend_from_slice(&self.follow_sequence(None, 9, rules));
+                //8张牌
+                actions.extend_from_slice(&self.follow_triple_straight(
+                    None,
+                    Carry::Single,
+                    2,
+                    rules,
+                ));
+                actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 4));
+                actions.extend_from_slice(&self.follow_sequence(None, 8, rules));
+                //7张牌
+                actions.extend_from_slice(&self.follow_sequence(None, 7, rules));
+                //6张牌
+                actions.extend_from_slice(&self.follow_straight(StraightType::Triple, None, 2));
+                actions.extend_from_slice(&self.follow_straight(StraightType::Pair, None, 3));
+                actions.extend_from_slice(&self.follow_sequence(None, 6, rules));
+                //5张牌
+                actions.extend_from_slice(&self.follow_triple(None, Carry::Pair));
+                actions.extend_from_slice(&self.follow_sequence(None, 5, rules));
+                //3张牌
+                actions.extend_from_slice(&self.follow_triple(None, Carry::None));
+                //4张牌
+                actions.extend_from_slice(&self.follow_triple(None, Carry::Single));
+                //2张牌
+                actions.extend_from_slice(&self.follow_pair(None));
+                //1张牌
+                actions.extend_from_slice(&self.follow_single(None));
+                //8张牌
+                actions.extend_from_slice(&self.follow_bomb_carry(None, Carry::Pair, rules));
+                //6张牌
+                actions.extend_from_slice(&self.follow_bomb_carry(None, Carry::Single, rules));
+                actions
+            }
+
+            fn follow_single(&self, card: Option<&Card>) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                let mut card = Self::plus(card);
 
-        while let Some(c) = card {
-            let pair = c as u64 | (c as u64) << 16;
-            let mut hand = *self;
-            if hand.0 & pair == pair {
-                hand.play_card(c);
-                hand.play_card(c);
-                actions.push((Action::Pair(c), hand));
+                loop {
+                    let Some(c) = card else {
+                        return actions;
+                    };
+
+                    let mut hand = *self;
+                    if hand.play_card(c).is_some() {
+                        actions.push((Action::Single(c), hand));
+                    }
+                    card = c.plus();
+                }
             }
 
-            if c == Card::Two {
-                return actions;
+            /// 单顺，短于规则的最少张数时没有
+            fn follow_sequence(
+                &self,
+                card: Option<&Card>,
+                length: u8,
+                rules: &RuleSet,
+            ) -> Vec<(Action, $hand)> {
+                if length < rules.min_sequence {
+                    return Vec::new();
+                }
+                self.follow_straight(StraightType::Single, card, length)
             }
-            card = c.plus();
-        }
 
-        actions
-    }
+            fn follow_straight(
+                &self,
+                st: StraightType,
+                card: Option<&Card>,
+                length: u8,
+            ) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                let mut card = Self::plus(card);
+                let mut straight = 0_u16;
+                let mut straight_start = card.unwrap();
+
+                for _ in 0..length {
+                    let Some(c) = card else { return actions };
+                    straight |= c as u16;
+                    card = c.plus();
+                }
 
-    fn follow_triple(&self, card: Option<&Card>, carry: Carry) -> Vec<(Action, Hand)> {
-        let mut actions = Vec::new();
-        let mut card = Self::plus(card);
-
-        while let Some(c) = card {
-            let triple = c as u64 | (c as u64) << 16 | (c as u64) << 32;
-            let mut hand = *self;
-            if hand.0 & triple == triple {
-                hand.play_card(c);
-                hand.play_card(c);
-                hand.play_card(c);
-                match carry {
-                    Carry::None => {
-                        actions.push((Action::Triplet(c), hand));
-                    }
-                    Carry::Single => {
-                        for (carry, hand) in hand.follow_triple_single(&c) {
-                            actions.push((Action::TripletSingle(c, carry), hand))
+                while straight < Card::Two as u16 {
+                    let action = match st {
+                        StraightType::Single => {
+                            self.follow_strait_single(straight, straight_start, length)
                         }
-                    }
-                    Carry::Pair => {
-                        for (carry, hand) in hand.follow_triple_pair(&c) {
-                            actions.push((Action::TripletPair(c, carry), hand))
+                        StraightType::Pair => {
+                            self.follow_straight_pair(straight as $bits, straight_start, length)
                         }
-                    }
+                        StraightType::Triple => {
+                            self.follow_straight_triple(straight as $bits, straight_start, length)
+                        }
+                    };
+                    actions.extend_from_slice(&action);
+                    straight_start = straight_start.plus().unwrap();
+                    straight <<= 1;
                 }
+
+                actions
             }
 
-            if c == Card::Two {
-                return actions;
+            fn follow_strait_single(
+                &self,
+                straight: u16,
+                straight_start: Card,
+                length: u8,
+            ) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                let mut hand = *self;
+
+                if hand.0 as u16 & straight == straight {
+                    let mut card = straight_start;
+                    for _ in 0..length {
+                        hand.play_card(card);
+                        card = card.plus().unwrap();
+                    }
+                    match length {
+                        5 => actions.push((Action::Sequence5(straight_start), hand)),
+                        6 => actions.push((Action::Sequence6(straight_start), hand)),
+                        7 => actions.push((Action::Sequence7(straight_start), hand)),
+                        8 => actions.push((Action::Sequence8(straight_start), hand)),
+                        9 => actions.push((Action::Sequence9(straight_start), hand)),
+                        10 => actions.push((Action::Sequence10(straight_start), hand)),
+                        11 => actions.push((Action::Sequence11(straight_start), hand)),
+                        12 => actions.push((Action::Sequence12, hand)),
+                        _ => unreachable!(),
+                    }
+                }
+                actions
             }
-            card = c.plus();
-        }
 
-        actions
-    }
+            fn follow_straight_pair(
+                &self,
+                straight: $bits,
+                straight_start: Card,
+                length: u8,
+            ) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                let mut hand = *self;
+                let straight = straight | straight << 16;
+
+                if hand.0 & straight == straight {
+                    let mut card = straight_start;
+                    for _ in 0..length {
+                        hand.play_card(card);
+                        hand.play_card(card);
+                        card = card.plus().unwrap();
+                    }
+                    match length {
+                        3 => actions.push((Action::PairSequence3(straight_start), hand)),
+                        4 => actions.push((Action::PairSequence4(straight_start), hand)),
+                        5 => actions.push((Action::PairSequence5(straight_start), hand)),
+                        6 => actions.push((Action::PairSequence6(straight_start), hand)),
+                        7 => actions.push((Action::PairSequence7(straight_start), hand)),
+                        8 => actions.push((Action::PairSequence8(straight_start), hand)),
+                        9 => actions.push((Action::PairSequence9(straight_start), hand)),
+                        10 => actions.push((Action::PairSequence10(straight_start), hand)),
+                        _ => unreachable!(),
+                    }
+                }
+                actions
+            }
 
-    fn follow_triple_single(&self, card: &Card) -> Vec<(Card, Hand)> {
-        let mut actions = Vec::new();
-        for (a, h) in self.follow_single(None) {
-            if let Action::Single(c) = a {
-                if c != *card {
-                    actions.push((c, h));
+            fn follow_straight_triple(
+                &self,
+                straight: $bits,
+                straight_start: Card,
+                length: u8,
+            ) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                let mut hand = *self;
+                let straight = straight | straight << 16 | straight << 32;
+
+                if hand.0 & straight == straight {
+                    let mut card = straight_start;
+                    for _ in 0..length {
+                        hand.play_card(card);
+                        hand.play_card(card);
+                        hand.play_card(card);
+                        card = card.plus().unwrap();
+                    }
+                    match length {
+                        2 => actions.push((Action::TripletSequence2(straight_start), hand)),
+                        3 => actions.push((Action::TripletSequence3(straight_start), hand)),
+                        4 => actions.push((Action::TripletSequence4(straight_start), hand)),
+                        5 => actions.push((Action::TripletSequence5(straight_start), hand)),
+                        6 => actions.push((Action::TripletSequence6(straight_start), hand)),
+                        _ => unreachable!(),
+                    }
                 }
+                actions
             }
-        }
 
-        actions
-    }
+            fn follow_pair(&self, card: Option<&Card>) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                let mut card = Self::plus(card);
+
+                while let Some(c) = card {
+                    if self.card_count(c) >= 2 {
+                        let mut hand = *self;
+                        hand.play_card(c);
+                        hand.play_card(c);
+                        actions.push((Action::Pair(c), hand));
+                    }
 
-    fn follow_triple_pair(&self, card: &Card) -> Vec<(Card, Hand)> {
-        let mut actions = Vec::new();
-        for (a, h) in self.follow_pair(None) {
-            if let Action::Pair(c) = a {
-                if c != *card {
-                    actions.push((c, h));
+                    // 两副牌时才有一对王
+                    if c == Card::Two && $decks < 2 {
+                        return actions;
+                    }
+                    card = c.plus();
                 }
+
+                actions
             }
-        }
 
-        actions
-    }
+            fn follow_triple(&self, card: Option<&Card>, carry: Carry) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                let mut card = Self::plus(card);
+
+                while let Some(c) = card {
+                    let triple = c as $bits | (c as $bits) << 16 | (c as $bits) << 32;
+                    let mut hand = *self;
+                    if hand.0 & triple == triple {
+                        hand.play_card(c);
+                        hand.play_card(c);
+                        hand.play_card(c);
+                        match carry {
+                            Carry::None => {
+                                actions.push((Action::Triplet(c), hand));
+                            }
+                            Carry::Single => {
+                                for (carry, hand) in hand.follow_triple_single(&c) {
+                                    actions.push((Action::TripletSingle(c, carry), hand))
+                                }
+                            }
+                            Carry::Pair => {
+                                for (carry, hand) in hand.follow_triple_pair(&c) {
+                                    actions.push((Action::TripletPair(c, carry), hand))
+                                }
+                            }
+                        }
+                    }
 
-    fn follow_triple_straight(
-        &self,
-        card: Option<&Card>,
-        carry: Carry,
-        length: u8,
-    ) -> Vec<(Action, Hand)> {
-        let mut actions = Vec::new();
-        let hand = *self;
-
-        for (action, hand) in hand.follow_straight(StraightType::Triple, card, length) {
-            let straight_start = match action {
-                Action::TripletSequence2(c) => c,
-                Action::TripletSequence3(c) => c,
-                Action::TripletSequence4(c) => c,
-                Action::TripletSequence5(c) => c,
-                Action::TripletSequence6(c) => c,
-                _ => unreachable!(),
-            };
-            match carry {
-                Carry::Single => {
-                    let mut carry_actions = Vec::new();
-                    Self::carry_single(&mut carry_actions, Vec::new(), hand, length);
-                    for (a, h) in carry_actions {
-                        match length {
-                            2 => actions.push((
-                                Action::TripletSequence2Single(straight_start, a[0], a[1]),
-                                h,
-                            )),
-                            3 => actions.push((
-                                Action::TripletSequence3Single(straight_start, a[0], a[1], a[2]),
-                                h,
-                            )),
-                            4 => actions.push((
-                                Action::TripletSequence4Single(
-                                    straight_start,
-                                    a[0],
-                                    a[1],
-                                    a[2],
-                                    a[3],
-                                ),
-                                h,
-                            )),
-                            5 => actions.push((
-                                Action::TripletSequence5Single(
-                                    straight_start,
-                                    a[0],
-                                    a[1],
-                                    a[2],
-                                    a[3],
-                                    a[4],
-                                ),
-                                h,
-                            )),
-                            _ => unreachable!(),
+                    if c == Card::Two {
+                        return actions;
+                    }
+                    card = c.plus();
+                }
+
+                actions
+            }
+
+            /// 三带的单牌，不能与三张同点数，否则就是炸弹
+            fn follow_triple_single(&self, card: &Card) -> Vec<(Card, $hand)> {
+                let mut actions = Vec::new();
+                for (a, h) in self.follow_single(None) {
+                    if let Action::Single(c) = a {
+                        if c != *card {
+                            actions.push((c, h));
                         }
                     }
                 }
-                Carry::Pair => {
-                    let mut carry_actions = Vec::new();
-                    Self::carry_pair(&mut carry_actions, Vec::new(), hand, length);
-                    for (a, h) in carry_actions {
-                        match length {
-                            2 => actions.push((
-                                Action::TripletSequence2Pair(straight_start, a[0], a[1]),
-                                h,
-                            )),
-                            3 => actions.push((
-                                Action::TripletSequence3Pair(straight_start, a[0], a[1], a[2]),
-                                h,
-                            )),
-                            4 => actions.push((
-                                Action::TripletSequence4Pair(
-                                    straight_start,
-                                    a[0],
-                                    a[1],
-                                    a[2],
-                                    a[3],
-                                ),
-                                h,
-                            )),
-                            _ => unreachable!(),
+
+                actions
+            }
+
+            fn follow_triple_pair(&self, card: &Card) -> Vec<(Card, $hand)> {
+                let mut actions = Vec::new();
+                for (a, h) in self.follow_pair(None) {
+                    if let Action::Pair(c) = a {
+                        if c != *card {
+                            actions.push((c, h));
                         }
                     }
                 }
-                _ => unreachable!(),
+
+                actions
             }
-        }
 
-        actions
-    }
+            /// 飞机带翅膀，翅膀中的炸弹、火箭和与飞机同点数的牌按规则取舍
+            fn follow_triple_straight(
+                &self,
+                card: Option<&Card>,
+                carry: Carry,
+                length: u8,
+                rules: &RuleSet,
+            ) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                let hand = *self;
+
+                for (action, hand) in hand.follow_straight(StraightType::Triple, card, length) {
+                    let straight_start = match action {
+                        Action::TripletSequence2(c) => c,
+                        Action::TripletSequence3(c) => c,
+                        Action::TripletSequence4(c) => c,
+                        Action::TripletSequence5(c) => c,
+                        Action::TripletSequence6(c) => c,
+                        _ => unreachable!(),
+                    };
+                    let end = (straight_start as u16) << length;
+                    let in_plane = |c: Card| c >= straight_start && (c as u16) < end;
+                    match carry {
+                        Carry::Single => {
+                            let mut carry_actions = Vec::new();
+                            Self::carry_single(&mut carry_actions, Vec::new(), hand, length);
+                            carry_actions.retain(|(a, _)| rules.allows_wings(a, false, in_plane));
+                            for (a, h) in carry_actions {
+                                match length {
+                                    2 => actions.push((
+                                        Action::TripletSequence2Single(straight_start, a[0], a[1]),
+                                        h,
+                                    )),
+                                    3 => actions.push((
+                                        Action::TripletSequence3Single(
+                                            straight_start,
+                                            a[0],
+                                            a[1],
+                                            a[2],
+                                        ),
+                                        h,
+                                    )),
+                                    4 => actions.push((
+                                        Action::TripletSequence4Single(
+                                            straight_start,
+                                            a[0],
+                                            a[1],
+                                            a[2],
+                                            a[3],
+                                        ),
+                                        h,
+                                    )),
+                                    5 => actions.push((
+                                        Action::TripletSequence5Single(
+                                            straight_start,
+                                            a[0],
+                                            a[1],
+                                            a[2],
+                                            a[3],
+                                            a[4],
+                                        ),
+                                        h,
+                                    )),
+                                    _ => unreachable!(),
+                                }
+                            }
+                        }
+                        Carry::Pair => {
+                            let mut carry_actions = Vec::new();
+                            Self::carry_pair(&mut carry_actions, Vec::new(), hand, length);
+                            carry_actions.retain(|(a, _)| rules.allows_wings(a, true, in_plane));
+                            for (a, h) in carry_actions {
+                                match length {
+                                    2 => actions.push((
+                                        Action::TripletSequence2Pair(straight_start, a[0], a[1]),
+                                        h,
+                                    )),
+                                    3 => actions.push((
+                                        Action::TripletSequence3Pair(
+                                            straight_start,
+                                            a[0],
+                                            a[1],
+                                            a[2],
+                                        ),
+                                        h,
+                                    )),
+                                    4 => actions.push((
+                                        Action::TripletSequence4Pair(
+                                            straight_start,
+                                            a[0],
+                                            a[1],
+                                            a[2],
+                                            a[3],
+                                        ),
+                                        h,
+                                    )),
+                                    _ => unreachable!(),
+                                }
+                            }
+                        }
+                        _ => unreachable!(),
+                    }
+                }
 
-    fn carry_single(
-        actions: &mut Vec<(Vec<Card>, Hand)>,
-        cards: Vec<Card>,
-        hand: Hand,
-        length: u8,
-    ) {
-        let mut min_card = None;
-        if let Some(card) = cards.last() {
-            min_card = card.minus();
-        }
-        hand.follow_single(min_card.as_ref())
-            .into_iter()
-            .for_each(|(a, h)| {
-                if let Action::Single(c) = a {
-                    let mut cards = cards.clone();
-                    cards.push(c);
-                    if length > 1 {
-                        Self::carry_single(actions, cards, h, length - 1);
-                    } else {
-                        actions.push((cards, h));
-                    }
-                }
-            });
-    }
+                actions
+            }
 
-    fn carry_pair(actions: &mut Vec<(Vec<Card>, Hand)>, cards: Vec<Card>, hand: Hand, length: u8) {
-        let mut min_card = None;
-        if let Some(card) = cards.last() {
-            min_card = card.minus();
-        }
-        hand.follow_pair(min_card.as_ref())
-            .into_iter()
-            .for_each(|(a, h)| {
-                if let Action::Single(c) = a {
-                    let mut cards = cards.clone();
-                    cards.push(c);
-                    if length > 1 {
-                        Self::carry_pair(actions, cards, h, length - 1);
-                    } else {
-                        actions.push((cards, h));
-                    }
-                }
-            });
-    }
+            fn carry_single(
+                actions: &mut Vec<(Vec<Card>, $hand)>,
+                cards: Vec<Card>,
+                hand: $hand,
+                length: u8,
+            ) {
+                let mut min_card = None;
+                if let Some(card) = cards.last() {
+                    min_card = card.minus();
+                }
+                hand.follow_single(min_card.as_ref())
+                    .into_iter()
+                    .for_each(|(a, h)| {
+                        if let Action::Single(c) = a {
+                            let mut cards = cards.clone();
+                            cards.push(c);
+                            if length > 1 {
+                                Self::carry_single(actions, cards, h, length - 1);
+                            } else {
+                                actions.push((cards, h));
+                            }
+                        }
+                    });
+            }
 
-    fn follow_bomb_carry(&self, card: Option<&Card>, carry: Carry) -> Vec<(Action, Hand)> {
-        let mut actions = Vec::new();
-        for (action, hand) in self.follow_bomb(card) {
-            if let Action::Bomb(c) = action {
-                let mut carry_actions = Vec::new();
-                match carry {
-                    Carry::Single => {
-                        Self::carry_single(&mut carry_actions, Vec::new(), hand, 2);
-                        for (a, h) in carry_actions {
-                            actions.push((Action::QuadSingle(c, a[0], a[1]), h));
+            fn carry_pair(
+                actions: &mut Vec<(Vec<Card>, $hand)>,
+                cards: Vec<Card>,
+                hand: $hand,
+                length: u8,
+            ) {
+                let mut min_card = None;
+                if let Some(card) = cards.last() {
+                    min_card = card.minus();
+                }
+                hand.follow_pair(min_card.as_ref())
+                    .into_iter()
+                    .for_each(|(a, h)| {
+                        if let Action::Pair(c) = a {
+                            let mut cards = cards.clone();
+                            cards.push(c);
+                            if length > 1 {
+                                Self::carry_pair(actions, cards, h, length - 1);
+                            } else {
+                                actions.push((cards, h));
+                            }
                         }
-                    }
-                    Carry::Pair => {
-                        Self::carry_pair(&mut carry_actions, Vec::new(), hand, 2);
-                        for (a, h) in carry_actions {
-                            actions.push((Action::QuadPair(c, a[0], a[1]), h));
+                    });
+            }
+
+            /// 四带二，规则允许时火箭也可以带，按四带二比大小
+            fn follow_bomb_carry(
+                &self,
+                card: Option<&Card>,
+                carry: Carry,
+                rules: &RuleSet,
+            ) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                let allowed = match carry {
+                    Carry::Single => rules.quad_single,
+                    Carry::Pair => rules.quad_pair,
+                    Carry::None => unreachable!(),
+                };
+                if !allowed {
+                    return actions;
+                }
+                for (action, hand) in self.follow_bomb(card) {
+                    let (c, rocket) = match action {
+                        Action::Bomb(c) => (c, false),
+                        Action::Rocket if rules.rocket_kickers => (Card::BlackJoker, true),
+                        _ => continue,
+                    };
+                    let mut carry_actions = Vec::new();
+                    match carry {
+                        Carry::Single => {
+                            Self::carry_single(&mut carry_actions, Vec::new(), hand, 2);
+                            carry_actions.retain(|(a, _)| rules.allows_wings(a, false, |k| k == c));
+                            for (a, h) in carry_actions {
+                                let action = if rocket {
+                                    Action::RocketSingle(a[0], a[1])
+                                } else {
+                                    Action::QuadSingle(c, a[0], a[1])
+                                };
+                                actions.push((action, h));
+                            }
                         }
+                        Carry::Pair => {
+                            Self::carry_pair(&mut carry_actions, Vec::new(), hand, 2);
+                            carry_actions.retain(|(a, _)| rules.allows_wings(a, true, |k| k == c));
+                            for (a, h) in carry_actions {
+                                let action = if rocket {
+                                    Action::RocketPair(a[0], a[1])
+                                } else {
+                                    Action::QuadPair(c, a[0], a[1])
+                                };
+                                actions.push((action, h));
+                            }
+                        }
+                        _ => unreachable!(),
                     }
-                    _ => unreachable!(),
                 }
+
+                actions
             }
-        }
 
-        actions
-    }
+            /// 比lead大的所有炸弹，lead为None时是所有炸弹。先按张数、再按点数从小到大，
+            /// 最后是火箭和天王炸；两副牌时两张王不算火箭
+            fn follow_bombs(&self, lead: Option<(u8, Card)>) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                for size in 4..=4 * $decks {
+                    // 整理过的手牌，第size段有某个点数就说明这个点数至少有size张
+                    let ranks =
+                        (self.0 >> (16 * (size - 1))) as u16 & (Card::BlackJoker as u16 - 1);
+                    if ranks == 0 {
+                        break;
+                    }
+                    let mut card = Some(Card::Three);
+                    while let Some(c) = card.filter(|&c| c <= Card::Two) {
+                        card = c.plus();
+                        if ranks & c as u16 == 0 {
+                            continue;
+                        }
+                        let action = match size {
+                            4 => Action::Bomb(c),
+                            5 => Action::Bomb5(c),
+                            6 => Action::Bomb6(c),
+                            7 => Action::Bomb7(c),
+                            _ => Action::Bomb8(c),
+                        };
+                        let mut hand = *self;
+                        for _ in 0..size {
+                            hand.play_card(c);
+                        }
+                        actions.push((action, hand));
+                    }
+                }
 
-    fn follow_bomb(&self, card: Option<&Card>) -> Vec<(Action, Hand)> {
-        let mut actions = Vec::new();
-        let mut card = Self::plus(card);
+                let (black, red) = (
+                    self.card_count(Card::BlackJoker),
+                    self.card_count(Card::RedJoker),
+                );
+                if $decks < 2 && black > 0 && red > 0 {
+                    let mut hand = *self;
+                    hand.play_card(Card::BlackJoker);
+                    hand.play_card(Card::RedJoker);
+                    actions.push((Action::Rocket, hand));
+                }
+                if black == 2 && red == 2 {
+                    actions.push((Action::KingBomb, self.without_jokers()));
+                }
 
-        while let Some(c) = card {
-            let cv = c as u64;
-            let bomb = cv | cv << 16 | cv << 32 | cv << 48;
-            let mut hand = *self;
+                actions.retain(|(action, _)| action.bomb_order() > lead);
+                actions
+            }
 
-            if hand.0 & bomb == bomb {
-                hand.0 &= !bomb;
-                actions.push((Action::Bomb(c), hand));
+            fn without_jokers(&self) -> $hand {
+                let mut hand = *self;
+                while hand.play_card(Card::BlackJoker).is_some() {}
+                while hand.play_card(Card::RedJoker).is_some() {}
+                hand
             }
-            card = c.plus();
-        }
 
-        let rocket = Card::BlackJoker as u64 | Card::RedJoker as u64;
-        if self.0 & rocket == rocket {
-            let mut hand = *self;
-            hand.0 &= !rocket;
-            actions.push((Action::Rocket, hand));
-        }
+            /// 四张的炸弹和火箭，用于四带二
+            fn follow_bomb(&self, card: Option<&Card>) -> Vec<(Action, $hand)> {
+                let mut actions = Vec::new();
+                let mut card = Self::plus(card);
+
+                let ranks = (self.0 >> 48) as u16;
+                while let Some(c) = card {
+                    let mut hand = *self;
+                    if c <= Card::Two && ranks & c as u16 != 0 {
+                        for _ in 0..4 {
+                            hand.play_card(c);
+                        }
+                        actions.push((Action::Bomb(c), hand));
+                    }
+                    card = c.plus();
+                }
 
-        actions
-    }
+                // 两副牌时两张王不算火箭
+                if $decks < 2
+                    && self.card_count(Card::BlackJoker) > 0
+                    && self.card_count(Card::RedJoker) > 0
+                {
+                    let mut hand = *self;
+                    hand.play_card(Card::BlackJoker);
+                    hand.play_card(Card::RedJoker);
+                    actions.push((Action::Rocket, hand));
+                }
 
-    fn plus(card: Option<&Card>) -> Option<Card> {
-        match card {
-            Some(c) => c.plus(),
-            None => Some(Card::Three),
+                actions
+            }
+
+            fn plus(card: Option<&Card>) -> Option<Card> {
+                match card {
+                    Some(c) => c.plus(),
+                    None => Some(Card::Three),
+                }
+            }
         }
-    }
-}
 
-impl Iterator for Hand {
-    type Item = SuitCard;
+        impl Iterator for $hand {
+            type Item = SuitCard;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut card = Card::RedJoker;
-        loop {
-            match self.play_card(card) {
-                None => {
-                    let next_card = card.minus()?;
-                    card = next_card;
+            fn next(&mut self) -> Option<Self::Item> {
+                let mut card = Card::RedJoker;
+                loop {
+                    match self.play_card(card) {
+                        None => {
+                            let next_card = card.minus()?;
+                            card = next_card;
+                        }
+                        s => return s,
+                    }
                 }
-                s => return s,
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                let size = self.size() as usize;
+                (size, Some(size))
             }
         }
-    }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let size = self.size() as usize;
-        (size, Some(size))
-    }
-}
+        impl Display for $hand {
+            /// 规范写法是按点数从小到大的牌面字符，如"3345XD"；
+            /// {:#}时带花色、用空格分隔，如"♠3 ♥3 ♦4 ♣5 X D"，能无损地解析回来
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                if f.alternate() {
+                    let cards = self
+                        .suit_cards()
+                        .iter()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<String>>();
+                    return write!(f, "{}", cards.join(" "));
+                }
+                let mut card = Some(Card::Three);
+                while let Some(c) = card {
+                    for _ in 0..self.card_count(c) {
+                        write!(f, "{}", c)?;
+                    }
+                    card = c.plus();
+                }
+                Ok(())
+            }
+        }
+        impl sealed::Sealed for $hand {
+            fn follow(&self, action: &Action, rules: &RuleSet) -> Vec<(Action, Self)> {
+                $hand::follow(self, action, rules)
+            }
 
-impl Display for Hand {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Hand: ")?;
-        for i in 0..16 {
-            if (self.0 >> i) & 1 == 1 {
-                write!(f, "{}", "34567890JQKA2BR".chars().nth(i).unwrap())?;
+            fn arrange(&self) -> Self {
+                $hand::arrange(self)
             }
-            if (self.0 >> (i + 16)) & 1 == 1 {
-                write!(f, "{}", "34567890JQKA2  ".chars().nth(i).unwrap())?;
+
+            fn card_count(&self, card: Card) -> u32 {
+                $hand::card_count(self, card)
             }
-            if (self.0 >> (i + 32)) & 1 == 1 {
-                write!(f, "{}", "34567890JQKA2  ".chars().nth(i).unwrap())?;
+
+            fn draw_card(&mut self, card: Card) {
+                $hand::draw_card(self, card)
             }
-            if (self.0 >> (i + 48)) & 1 == 1 {
-                write!(f, "{}", "34567890JQKA2  ".chars().nth(i).unwrap())?;
+
+            fn play_card(&mut self, card: Card) -> Option<SuitCard> {
+                $hand::play_card(self, card)
             }
         }
 
-        Ok(())
-    }
+        impl Cards for $hand {
+            const DECKS: u8 = $decks;
+            const MAX_PLAYERS: usize = $players;
+            type Hands = [$hand; $players];
+
+            fn size(&self) -> u32 {
+                $hand::size(self)
+            }
+
+            fn is_empty(&self) -> bool {
+                $hand::is_empty(self)
+            }
+        }
+    };
 }
 
+hand!(Hand, u64, 1, 3);
+hand!(DoubleHand, u128, 2, 4);
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{BeatError, Category};
 
     #[test]
     fn test_draw() {
@@ -757,4 +1333,319 @@ mod tests {
 
         assert_eq!(hand.0, Card::BlackJoker as u64 | Card::RedJoker as u64);
     }
+
+    #[test]
+    fn test_legal_moves() {
+        let hand = Hand::from("3345");
+        let moves = hand.legal_moves(None);
+        let played = moves
+            .iter()
+            .map(|(c, _)| c.unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(played, vec!["33", "3", "4", "5"]);
+        assert_eq!(moves[0].1, Hand::from("45"));
+
+        // 跟牌：同类牌型、炸弹，最后是“不要”
+        let hand = Hand::from("3456666");
+        let lead = "4".parse::<Combination>().unwrap();
+        let moves = hand.legal_moves(Some(&lead));
+        let played = moves
+            .iter()
+            .map(|(c, _)| c.map(|c| c.to_string()).unwrap_or_default())
+            .collect::<Vec<String>>();
+        assert_eq!(played, vec!["5", "6", "6666", ""]);
+        assert_eq!(moves.last().unwrap().1, hand);
+
+        // 剩下的手牌保留花色
+        let mut hand = Hand::default();
+        hand.insert_suit_card(SuitCard::Spades(Card::Three));
+        hand.insert_suit_card(SuitCard::Hearts(Card::Four));
+        let (_, rest) = hand.legal_moves(None)[0];
+        assert_eq!(rest.0, Hand::bit(SuitCard::Hearts(Card::Four), 0));
+    }
+
+    #[test]
+    fn test_carry_pair() {
+        // 飞机带对子、四带两对，不会重复生成
+        let actions = Hand::from("3334445566")
+            .arrange()
+            .follow(&Action::None, &RuleSet::default());
+        assert!(actions.contains(&(
+            Action::TripletSequence2Pair(Card::Three, Card::Five, Card::Six),
+            Hand::default()
+        )));
+        let actions = Hand::from("22223344")
+            .arrange()
+            .follow(&Action::None, &RuleSet::default());
+        assert!(actions.contains(&(
+            Action::QuadPair(Card::Two, Card::Three, Card::Four),
+            Hand::default()
+        )));
+        let actions = Hand::from("222234")
+            .arrange()
+            .follow(&Action::None, &RuleSet::default());
+        for (i, (action, _)) in actions.iter().enumerate() {
+            assert!(
+                !actions[i + 1..].iter().any(|(a, _)| a == action),
+                "{}",
+                action
+            );
+        }
+        assert!(actions.contains(&(
+            Action::QuadSingle(Card::Two, Card::Three, Card::Four),
+            Hand::default()
+        )));
+
+        // 跟牌时也能带对子
+        let lead = Action::TripletSequence2Pair(Card::Three, Card::Five, Card::Six);
+        let actions = Hand::from("5556667788")
+            .arrange()
+            .follow(&lead, &RuleSet::default());
+        assert!(actions.contains(&(
+            Action::TripletSequence2Pair(Card::Five, Card::Seven, Card::Eight),
+            Hand::default()
+        )));
+    }
+
+    #[test]
+    fn test_laizi() {
+        let rules = RuleSet {
+            laizi: Some(Card::Seven),
+            ..Default::default()
+        };
+        // 7当作3或5凑对子，剩下的手牌中少的是7
+        let hand = Hand::from("357");
+        let moves = hand.legal_moves_with(None, &rules);
+        let played = moves
+            .iter()
+            .map(|(c, _)| c.unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(played, vec!["3", "5", "7", "33", "55"]);
+        assert_eq!(moves[3].1, Hand::from("5"));
+        assert_eq!(moves[4].1, Hand::from("3"));
+
+        // 用了癞子的是软炸弹，管不上硬炸弹，硬炸弹都能管上软炸弹
+        let moves = Hand::from("5557").legal_moves_with(None, &rules);
+        let soft = moves
+            .iter()
+            .find_map(|(c, rest)| {
+                c.filter(|c| c.category() == Category::SoftBomb && rest.is_empty())
+            })
+            .unwrap();
+        assert_eq!(soft.rank(), Card::Five);
+        let hard = "3333".parse::<Combination>().unwrap();
+        assert_eq!(soft.beats(&hard), Err(BeatError::HardBombPrecedence));
+        assert_eq!(hard.beats(&soft), Ok(()));
+        assert_eq!(
+            Hand::from("5557")
+                .legal_moves_with(Some(&hard), &rules)
+                .len(),
+            1
+        );
+        let moves = Hand::from("3333").legal_moves_with(Some(&soft), &rules);
+        assert_eq!(moves.len(), 2);
+
+        // 软炸弹之间比点数，癞子不能当大小王
+        let moves = Hand::from("4447").legal_moves_with(Some(&soft), &rules);
+        assert_eq!(moves.len(), 1);
+        let moves = Hand::from("6667").legal_moves_with(Some(&soft), &rules);
+        assert_eq!(moves.len(), 2);
+        let moves = Hand::from("X7").legal_moves_with(None, &rules);
+        assert_eq!(moves.len(), 2);
+
+        // 癞子替成带的牌和本来的出法等价，不重复生成
+        let moves = Hand::from("33347").legal_moves_with(None, &rules);
+        for (i, (a, rest)) in moves.iter().enumerate() {
+            let a = a.unwrap().0;
+            assert!(
+                !moves[..i]
+                    .iter()
+                    .any(|(b, r)| r == rest && a.same_but_kickers(&b.unwrap().0)),
+                "{}",
+                a
+            );
+        }
+        let moves = moves
+            .iter()
+            .map(|(c, _)| c.unwrap().to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            moves,
+            vec![
+                "333", "3334", "3337", "33", "3", "4", "7", "333", "3334", "33", "3333", "33344",
+                "44"
+            ]
+        );
+
+        // 两副牌时癞子凑成的五张也是软炸弹，比五张的硬炸弹小，比四张的炸弹大
+        let moves = DoubleHand::from("55557").legal_moves_with(None, &rules);
+        let soft = moves
+            .iter()
+            .find_map(|(c, rest)| c.filter(|_| rest.is_empty()))
+            .unwrap();
+        assert_eq!(soft.0, Action::SoftBomb5(Card::Five));
+        let hard = Combination(Action::Bomb5(Card::Three));
+        assert_eq!(soft.beats(&hard), Err(BeatError::HardBombPrecedence));
+        assert_eq!(hard.beats(&soft), Ok(()));
+        assert_eq!(soft.beats(&Combination(Action::Bomb(Card::Two))), Ok(()));
+        assert_eq!(
+            Combination(Action::SoftBomb(Card::Two)).beats(&hard),
+            Err(BeatError::BombPrecedence)
+        );
+    }
+
+    #[test]
+    fn test_two_decks() {
+        // 一副牌的手牌仍是u64，两副牌另用DoubleHand
+        assert_eq!(std::mem::size_of::<Hand>(), 8);
+        assert_eq!(DOUBLE_DECK.size(), 108);
+        let mut hand = DoubleHand::default();
+        hand.insert_suit_card(SuitCard::Spades(Card::Ace));
+        hand.insert_suit_card(SuitCard::Spades(Card::Ace));
+        assert_eq!(hand.size(), 2);
+        hand.remove_suit_card(SuitCard::Spades(Card::Ace));
+        assert!(hand.contains(SuitCard::Spades(Card::Ace)));
+
+        let rules = RuleSet::default();
+        let hand = DoubleHand::from("33333333XXDD");
+        assert_eq!(hand.card_count(Card::Three), 8);
+        let moves = hand.legal_moves_with(None, &rules);
+        let played = moves
+            .iter()
+            .map(|(c, _)| c.unwrap().to_string())
+            .collect::<Vec<String>>();
+        for bomb in ["3333", "33333", "333333", "3333333", "33333333", "XXDD"] {
+            assert!(played.contains(&bomb.to_string()), "{}", bomb);
+        }
+        // 两副牌时两张王不是火箭
+        assert!(!played.contains(&"XD".to_string()));
+        assert_eq!(DoubleHand::from("XD").legal_moves(None).len(), 2);
+        assert_eq!(Hand::from("XD").legal_moves(None).len(), 3);
+        // 两副牌有一对王，与classify一致
+        for pair in ["XX", "DD"] {
+            assert!(played.contains(&pair.to_string()), "{}", pair);
+        }
+        let twos = Combination::classify_str::<DoubleHand>("22").unwrap();
+        let moves = DoubleHand::from("XXD").legal_moves(Some(&twos));
+        assert!(moves
+            .iter()
+            .any(|(c, _)| c
+                .is_some_and(|c| c == Combination::classify_str::<DoubleHand>("XX").unwrap())));
+        let rocket_kickers = RuleSet {
+            rocket_kickers: true,
+            ..Default::default()
+        };
+        assert!(DoubleHand::from("34XD")
+            .legal_moves_with(None, &rocket_kickers)
+            .iter()
+            .all(|(c, _)| c.unwrap().to_string() != "XD34"));
+
+        // 炸弹先比张数再比点数，天王炸最大
+        let bomb = |s: &str| Combination::classify_str::<DoubleHand>(s).unwrap();
+        assert_eq!(bomb("33333").beats(&bomb("2222")), Ok(()));
+        assert_eq!(
+            bomb("2222").beats(&bomb("33333")),
+            Err(BeatError::BombPrecedence)
+        );
+        assert_eq!(
+            bomb("33333").beats(&bomb("44444")),
+            Err(BeatError::RankTooLow)
+        );
+        assert_eq!(bomb("XXDD").beats(&bomb("22222222")), Ok(()));
+        assert_eq!(
+            Combination(Action::Rocket).beats(&bomb("XXDD")),
+            Err(BeatError::KingBombPrecedence)
+        );
+        let moves = DoubleHand::from("444455555").legal_moves_with(Some(&bomb("33333")), &rules);
+        assert_eq!(moves.len(), 2);
+        assert_eq!(moves[0].1, DoubleHand::from("4444"));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("33 4x".parse::<Hand>().unwrap(), Hand::from("334X"));
+        assert!(matches!(
+            "3345y".parse::<Hand>(),
+            Err(Error::UnknownCard('y', 4))
+        ));
+        assert!(matches!(
+            "33333".parse::<Hand>(),
+            Err(Error::TooManyOfRank(Card::Three, 4))
+        ));
+        assert!(matches!(
+            "3DD".parse::<Hand>(),
+            Err(Error::TooManyJokers(Card::RedJoker, 1))
+        ));
+        assert!(matches!(" ".parse::<Hand>(), Err(Error::Empty)));
+        assert_eq!("33333XXDD".parse::<DoubleHand>().unwrap().size(), 9);
+        assert_eq!(Error::UnknownCard('y', 4).to_string(), "第5个字符不是牌: y");
+    }
+
+    #[test]
+    fn test_notation() {
+        let hand = Hand::from("D53X4T3");
+        assert_eq!(hand.to_string(), "3345TXD");
+        assert_eq!(hand.to_string().parse::<Hand>().unwrap(), hand.arrange());
+
+        let mut hand = Hand::default();
+        for c in ["♦3", "♠3", "♥A", "♣2", "X"] {
+            hand.insert_suit_card(c.parse().unwrap());
+        }
+        assert_eq!(format!("{:#}", hand), "♠3 ♦3 ♥A ♣2 X");
+        assert_eq!(format!("{:#}", hand).parse::<Hand>().unwrap(), hand);
+        assert_eq!("s3 f3 hA c2 x".parse::<Hand>().unwrap(), hand);
+
+        // 只有方块时也能来回转换，字母D仍是大王
+        let mut hand = Hand::default();
+        for c in ["♦3", "♦4", "D"] {
+            hand.insert_suit_card(c.parse().unwrap());
+        }
+        assert_eq!(format!("{:#}", hand), "♦3 ♦4 D");
+        assert_eq!(format!("{:#}", hand).parse::<Hand>().unwrap(), hand);
+        assert_eq!("F3 f4 D".parse::<Hand>().unwrap(), hand);
+        assert_eq!("D3".parse::<Hand>().unwrap(), Hand::from("3D"));
+
+        // 两副牌：同一张牌出现两次
+        let mut hand = DoubleHand::default();
+        for c in ["♠3", "♠3", "♥3", "X", "X", "D"] {
+            hand.insert_suit_card(c.parse().unwrap());
+        }
+        assert_eq!(format!("{:#}", hand), "♠3 ♥3 ♠3 X X D");
+        assert_eq!(format!("{:#}", hand).parse::<DoubleHand>().unwrap(), hand);
+        assert!(matches!(
+            "♠3 ♠3".parse::<Hand>(),
+            Err(Error::DuplicateCard(c)) if c == SuitCard::new(Card::Three, 3)
+        ));
+
+        // 出错时指出第一个不认识的字符
+        assert!(matches!(
+            "♠3 ♥y".parse::<Hand>(),
+            Err(Error::UnknownCard('y', 4))
+        ));
+        assert!(matches!(
+            "♠3 z3".parse::<Hand>(),
+            Err(Error::UnknownCard('z', 3))
+        ));
+        assert!(matches!(
+            "♠3 3".parse::<Hand>(),
+            Err(Error::UnknownCard('3', 3))
+        ));
+        assert!(matches!(
+            "♠3 ♥33".parse::<Hand>(),
+            Err(Error::UnknownCard('3', 5))
+        ));
+        // 王带了花色时单独报错
+        assert!(matches!(
+            "♠3 ♠X".parse::<Hand>(),
+            Err(Error::SuitedJoker(Card::BlackJoker, 3))
+        ));
+        assert_eq!(
+            "♠3 ♠X".parse::<Hand>().unwrap_err().to_string(),
+            "第4个字符: X不能有花色"
+        );
+        assert!(matches!(
+            "♥D".parse::<SuitCard>(),
+            Err(Error::SuitedJoker(Card::RedJoker, 0))
+        ));
+    }
 }
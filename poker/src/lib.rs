@@ -1,10 +1,24 @@
-pub use error::Error;
-pub use game::{Game, State};
 pub use card::{Card, SuitCard};
-pub use hand::{DECK_OF_CARDS, Hand};
+pub use combination::{Category, Combination};
+pub use deal::{Deal, MoveOdds};
+pub use error::{BeatError, Error};
+pub use game::{Config, Game, Mode, Outcome, Progress, State, Stats};
+pub use hand::{Cards, DoubleHand, Hand, DECK_OF_CARDS, DOUBLE_DECK};
+pub use rules::RuleSet;
 
 pub(crate) mod action;
 pub(crate) mod card;
+pub(crate) mod combination;
+pub(crate) mod deal;
 pub(crate) mod error;
+pub(crate) mod export;
 pub(crate) mod game;
 pub(crate) mod hand;
+#[cfg(feature = "parallel")]
+pub(crate) mod parallel;
+pub(crate) mod robust;
+pub(crate) mod rules;
+pub(crate) mod save;
+pub(crate) mod search;
+#[cfg(feature = "serde")]
+pub(crate) mod serialize;
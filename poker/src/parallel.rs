@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use indextree::{Arena, NodeId};
+use rayon::prelude::*;
+
+use crate::game::{Budget, Config, Game, Mode, Outcome, State, ALL_WINS_NODES};
+use crate::hand::Cards;
+
+/// 根节点的各个出法分给线程池并行求解，再把各自的方案合并成一棵树
+pub(crate) fn solve<H: Cards>(mut state: State<H>, config: Config, mut budget: Budget) -> Game<H> {
+    let ours = state.our_turn();
+    let follow = state.player.as_ref()[state.turn as usize].follow(&state.lead, &config.rules);
+    budget.spend();
+
+    // 能一手出完的，不用再分给线程池
+    if let Some(&(action, hand)) = follow.iter().find(|(_, hand)| hand.is_empty()) {
+        let mut arena = Arena::new();
+        let child = state.next(action, hand);
+        state.pass = ours;
+        state.expanded = true;
+        let root = arena.new_node(state);
+        if ours {
+            let child = arena.new_node(child);
+            root.append(child, &mut arena);
+        }
+        budget.finish();
+        return Game::from_parts(arena, root, config, budget);
+    }
+
+    // 我方找到必胜出法，或者对方找到反例后，其余出法不必再算
+    let exhaustive = ours && (config.mode == Mode::Shortest || config.all_wins);
+    let stop = Arc::new(AtomicBool::new(false));
+    let sub_config = Config {
+        parallel: false,
+        ..config.clone()
+    };
+    let games = follow
+        .into_par_iter()
+        .map(|(action, hand)| {
+            let game = Game::with_budget(
+                state.next(action, hand),
+                sub_config.clone(),
+                budget.worker(&stop),
+            );
+            let decided = match game.outcome() {
+                Outcome::Win => ours,
+                Outcome::Loss => !ours,
+                Outcome::Unknown => false,
+            };
+            if decided && !exhaustive {
+                stop.store(true, Ordering::Relaxed);
+            }
+            game
+        })
+        .collect::<Vec<Game<H>>>();
+
+    for game in &games {
+        budget.stats.absorb(&game.stats());
+    }
+
+    let (selected, outcome) = if ours {
+        let mut wins = games.iter().filter(|g| g.pass()).collect::<Vec<&Game<H>>>();
+        if config.mode == Mode::Shortest {
+            wins.sort_by_key(|g| g.our_turns(g.root));
+        }
+        if !config.all_wins {
+            wins.truncate(1);
+        }
+        let outcome = if !wins.is_empty() {
+            Outcome::Win
+        } else if games.iter().any(|g| g.outcome() == Outcome::Unknown) {
+            Outcome::Unknown
+        } else {
+            Outcome::Loss
+        };
+        (wins, outcome)
+    } else {
+        let outcome = if games.iter().any(|g| g.outcome() == Outcome::Loss) {
+            Outcome::Loss
+        } else if games.iter().any(|g| g.outcome() == Outcome::Unknown) {
+            Outcome::Unknown
+        } else {
+            Outcome::Win
+        };
+        (games.iter().collect(), outcome)
+    };
+
+    let mut arena = Arena::new();
+    state.pass = outcome == Outcome::Win;
+    state.expanded = true;
+    let root = arena.new_node(state);
+    if outcome == Outcome::Win {
+        for (i, game) in selected.into_iter().enumerate() {
+            // 保留所有必胜出法时，方案树的节点太多就不再合并其余出法
+            if ours && i > 0 && arena.len() >= ALL_WINS_NODES {
+                break;
+            }
+            graft(&mut arena, root, game, game.root);
+        }
+    }
+    budget.exhausted = outcome == Outcome::Unknown;
+    budget.finish();
+    Game::from_parts(arena, root, config, budget)
+}
+
+/// 把子局面的方案复制到parent下
+fn graft<H: Cards>(arena: &mut Arena<State<H>>, parent: NodeId, game: &Game<H>, node_id: NodeId) {
+    let child = arena.new_node(game.arena[node_id].get().clone());
+    parent.append(child, arena);
+    for n in node_id.children(&game.arena) {
+        graft(arena, child, game, n);
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use indextree::{Arena, NodeId};
+
+use crate::action::Action;
+use crate::game::{Budget, Config, Position, State};
+use crate::hand::Hand;
+use crate::rules::RuleSet;
+use crate::search::entry_size;
+
+/// 没有设置table_bytes时置换表最多记录的信息集数，超出后清空重来
+const TABLE_CAPACITY: usize = 1 << 18;
+
+/// 信息集上的与或搜索：每个信息集是公开信息相同的所有可能局面。
+/// 我方只能根据公开信息出牌，同一种出法要在所有局面下都必胜；
+/// 其他玩家（包括队友）在任一局面下能出的牌都要应对，出牌后只保留出得了这手牌的局面
+pub(crate) struct Robust {
+    /// 置换表：信息集 -> 我方是否必胜
+    table: HashMap<Vec<Position<Hand>>, bool>,
+    /// 置换表最多占用的字节数，None时按记录数限制
+    table_bytes: Option<usize>,
+    /// 置换表已占用的字节数，信息集的局面数不同，逐条累计
+    used_bytes: usize,
+    pub(crate) budget: Budget,
+    rules: RuleSet,
+}
+
+impl Robust {
+    pub(crate) fn new(config: &Config, budget: Budget) -> Self {
+        Robust {
+            table: HashMap::new(),
+            table_bytes: config.table_bytes,
+            used_bytes: 0,
+            budget,
+            rules: config.rules,
+        }
+    }
+
+    /// 求解并只生成必胜方案的子树，返回根节点
+    pub(crate) fn strategy(&mut self, arena: &mut Arena<State>, worlds: Vec<State>) -> NodeId {
+        let worlds = normalize(worlds);
+        let won = self.solve(&worlds);
+        let mut state = public(&worlds[0]);
+        state.pass = won && !self.budget.exhausted;
+        let root = arena.new_node(state);
+        if won && !self.budget.exhausted {
+            self.build(arena, root, worlds);
+        }
+        // 生成方案时预算用完，方案不完整，按胜负未知处理
+        if self.budget.exhausted {
+            for child in root.children(arena).collect::<Vec<NodeId>>() {
+                child.remove_subtree(arena);
+            }
+            arena.get_mut(root).unwrap().get_mut().pass = false;
+        }
+        self.table = HashMap::new();
+        self.used_bytes = 0;
+        root
+    }
+
+    /// 当前信息集我方是否必胜
+    fn solve(&mut self, worlds: &[State]) -> bool {
+        let key = worlds
+            .iter()
+            .map(|w| w.position())
+            .collect::<Vec<Position<Hand>>>();
+        if let Some(&won) = self.table.get(&key) {
+            self.budget.stats.transpositions += 1;
+            return won;
+        }
+        if !self.budget.spend() {
+            return false;
+        }
+
+        let won = match self.moves(worlds) {
+            None => worlds[0].our_turn(),
+            Some(moves) if worlds[0].turn == 0 => {
+                moves.into_iter().any(|(_, next)| self.solve(&next))
+            }
+            Some(moves) => moves.into_iter().all(|(_, next)| self.solve(&next)),
+        };
+
+        if self.budget.exhausted {
+            return won;
+        }
+        let size = entry_size::<Vec<Position<Hand>>, bool>()
+            + key.len() * std::mem::size_of::<Position<Hand>>();
+        let full = match self.table_bytes {
+            Some(bytes) => self.used_bytes + size > bytes,
+            None => self.table.len() >= TABLE_CAPACITY,
+        };
+        if full {
+            log::debug!("置换表已满，清空");
+            self.table.clear();
+            self.used_bytes = 0;
+        }
+        self.used_bytes += size;
+        self.table.insert(key, won);
+        won
+    }
+
+    /// 当前玩家在各个局面下能出的牌，按出法分组。
+    /// 有局面能一手出完时返回None，这一方直接获胜
+    fn moves(&self, worlds: &[State]) -> Option<Vec<(Action, Vec<State>)>> {
+        let turn = worlds[0].turn as usize;
+        let mut moves: Vec<(Action, Vec<State>)> = Vec::new();
+        for world in worlds {
+            for (action, hand) in world.player[turn].follow(&world.lead, &self.rules) {
+                if hand.is_empty() {
+                    // 队友出完也是我方获胜，不用应对
+                    if turn != 0 && world.our_turn() {
+                        continue;
+                    }
+                    // 我方的牌是确定的；对方只要有一个局面能出完，就不能保证必胜
+                    return None;
+                }
+                let next = world.next(action, hand);
+                match moves.iter_mut().find(|(a, _)| *a == action) {
+                    Some((_, group)) => group.push(next),
+                    None => moves.push((action, vec![next])),
+                }
+            }
+        }
+        Some(
+            moves
+                .into_iter()
+                .map(|(action, group)| (action, normalize(group)))
+                .collect(),
+        )
+    }
+
+    /// 在已证明必胜的信息集下，展开我方的一种必胜出法，或其他玩家的所有出法
+    fn build(&mut self, arena: &mut Arena<State>, node_id: NodeId, worlds: Vec<State>) {
+        arena.get_mut(node_id).unwrap().get_mut().expanded = true;
+        let Some(moves) = self.moves(&worlds) else {
+            // 我方一手出完
+            let world = &worlds[0];
+            let turn = world.turn as usize;
+            if let Some((action, hand)) = world.player[turn]
+                .follow(&world.lead, &self.rules)
+                .into_iter()
+                .find(|(_, hand)| hand.is_empty())
+            {
+                if !self.budget.grow() {
+                    return;
+                }
+                let child = arena.new_node(public(&world.next(action, hand)));
+                node_id.append(child, arena);
+            }
+            return;
+        };
+
+        let ours = worlds[0].turn == 0;
+        for (_, next) in moves {
+            if ours && !self.solve(&next) {
+                continue;
+            }
+            // 预算用完时方案不完整，由strategy删除
+            if !self.budget.grow() {
+                return;
+            }
+            let mut state = public(&next[0]);
+            state.pass = true;
+            let child = arena.new_node(state);
+            node_id.append(child, arena);
+            self.build(arena, child, next);
+            if ours {
+                break;
+            }
+        }
+    }
+}
+
+/// 排序并去掉重复的局面，使相同的信息集得到相同的键
+fn normalize(mut worlds: Vec<State>) -> Vec<State> {
+    worlds.sort_by_key(|w| w.player.map(|h| h.value()));
+    worlds.dedup();
+    worlds
+}
+
+/// 方案中的节点只保留公开信息，其他玩家的手牌未知，置空
+fn public(world: &State) -> State {
+    let mut state = world.clone();
+    for hand in state.player.iter_mut().skip(1) {
+        *hand = Hand::default();
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Config, Deal, Game, Mode};
+
+    #[test]
+    fn test_robust() {
+        // 对方一张牌，不论是A还是4，先出KK再出3都必胜
+        let deal = Deal {
+            hand: "KK3".into(),
+            unseen: "A4".into(),
+            counts: vec![1],
+            ..Default::default()
+        };
+        let game = Game::robust(&deal, Config::default()).unwrap();
+        assert!(game.pass());
+        let (cards, replies) = game.action(None);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(replies.len(), 1);
+
+        // 对方是A时必败
+        let deal = Deal {
+            hand: "K3".into(),
+            ..deal
+        };
+        assert!(!Game::robust(&deal, Config::default()).unwrap().pass());
+
+        // 对方是44时要先出4，是A4时要先出33，每种发牌都能赢，但没有通用的方案
+        let deal = Deal {
+            hand: "334".into(),
+            unseen: "A44".into(),
+            counts: vec![2],
+            ..Default::default()
+        };
+        let worlds = deal.worlds().unwrap();
+        assert_eq!(worlds.len(), 2);
+        for world in worlds {
+            assert!(Game::with_mode(world, Mode::Search).pass());
+        }
+        assert!(!Game::robust(&deal, Config::default()).unwrap().pass());
+    }
+
+    #[test]
+    fn test_robust_is_sound() {
+        // 信息集上必胜，则每种发牌下都必胜
+        for hand in ["334", "KK3", "QK2", "3345", "5566"] {
+            for unseen in ["A4", "AA44", "2A4", "5A4"] {
+                let deal = Deal {
+                    hand: hand.into(),
+                    unseen: unseen.into(),
+                    counts: vec![2],
+                    ..Default::default()
+                };
+                let pass = Game::robust(&deal, Config::default()).unwrap().pass();
+                let small = Config {
+                    table_bytes: Some(256),
+                    ..Default::default()
+                };
+                assert_eq!(Game::robust(&deal, small).unwrap().pass(), pass);
+                if pass {
+                    for world in deal.worlds().unwrap() {
+                        assert!(Game::with_mode(world, Mode::Search).pass());
+                    }
+                }
+            }
+        }
+    }
+}
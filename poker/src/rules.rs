@@ -0,0 +1,167 @@
+use crate::card::Card;
+
+/// 各地玩法不同的规则，默认是最常见的玩法
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RuleSet {
+    /// 允许四带二单
+    pub quad_single: bool,
+    /// 允许四带二对
+    pub quad_pair: bool,
+    /// 飞机、四带二带的牌中可以有炸弹（同点数四张）或火箭
+    pub bomb_in_wings: bool,
+    /// 飞机、四带二带的牌可以与主体的点数相同；三带一、三带一对总是不能带同点数的牌
+    pub kicker_same_rank: bool,
+    /// 顺子最少的张数，小于5按5算
+    pub min_sequence: u8,
+    /// 火箭可以带二单或二对，按四带二算，比四个2带的大，但能被炸弹管上
+    pub rocket_kickers: bool,
+    /// 癞子的点数，癞子可以当作除大小王以外的任意点数
+    pub laizi: Option<Card>,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet {
+            quad_single: true,
+            quad_pair: true,
+            bomb_in_wings: true,
+            kicker_same_rank: true,
+            min_sequence: 5,
+            rocket_kickers: false,
+            laizi: None,
+        }
+    }
+}
+
+impl RuleSet {
+    /// 飞机、四带二带的牌是否允许。wings是带的牌的点数，pair时每个点数是一对；
+    /// same_rank判断点数是否与主体相同
+    pub(crate) fn allows_wings(
+        &self,
+        wings: &[Card],
+        pair: bool,
+        same_rank: impl Fn(Card) -> bool,
+    ) -> bool {
+        if !self.kicker_same_rank && wings.iter().any(|&c| same_rank(c)) {
+            return false;
+        }
+        if self.bomb_in_wings {
+            return true;
+        }
+        let per = if pair { 2 } else { 1 };
+        let bomb = wings
+            .iter()
+            .any(|c| wings.iter().filter(|&k| k == c).count() * per >= 4);
+        let rocket = wings.contains(&Card::BlackJoker) && wings.contains(&Card::RedJoker);
+        !bomb && !rocket
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Action;
+    use crate::combination::{Category, Combination};
+    use crate::hand::Hand;
+
+    /// 自由出牌时能出的所有牌
+    fn leads(hand: &str, rules: RuleSet) -> Vec<String> {
+        Hand::from(hand)
+            .legal_moves_with(None, &rules)
+            .into_iter()
+            .map(|(c, _)| c.unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_quad() {
+        let rules = RuleSet {
+            quad_single: false,
+            ..Default::default()
+        };
+        let moves = leads("22223344", rules);
+        assert!(moves.contains(&"22223344".to_string()));
+        assert!(!moves.contains(&"222234".to_string()));
+
+        let rules = RuleSet {
+            quad_pair: false,
+            ..Default::default()
+        };
+        let moves = leads("22223344", rules);
+        assert!(!moves.contains(&"22223344".to_string()));
+        assert!(moves.contains(&"222234".to_string()));
+    }
+
+    #[test]
+    fn test_wings() {
+        // 飞机带的牌与三张同点数，默认允许
+        let hand = "33334445";
+        assert!(leads(hand, RuleSet::default()).contains(&"33344435".to_string()));
+        let rules = RuleSet {
+            kicker_same_rank: false,
+            ..Default::default()
+        };
+        assert!(!leads(hand, rules).contains(&"33344435".to_string()));
+        // 三带一不能带同点数的牌，3333只是炸弹
+        assert_eq!(leads("3333", RuleSet::default()).len(), 4);
+        let rules = RuleSet {
+            kicker_same_rank: true,
+            ..Default::default()
+        };
+        assert_eq!(leads("3333", rules).len(), 4);
+
+        // 带的牌中有炸弹或火箭
+        let hand = "333444555666777788XD";
+        let plane = "3334445556667777".to_string();
+        let rocket = "333444XD".to_string();
+        assert!(leads(hand, RuleSet::default()).contains(&plane));
+        assert!(leads(hand, RuleSet::default()).contains(&rocket));
+        let rules = RuleSet {
+            bomb_in_wings: false,
+            ..Default::default()
+        };
+        assert!(!leads(hand, rules).contains(&plane));
+        assert!(!leads(hand, rules).contains(&rocket));
+        assert!(leads(hand, rules).contains(&"3334445556667788".to_string()));
+    }
+
+    #[test]
+    fn test_min_sequence() {
+        let rules = RuleSet {
+            min_sequence: 6,
+            ..Default::default()
+        };
+        let moves = leads("3456789", rules);
+        assert!(!moves.contains(&"34567".to_string()));
+        assert!(moves.contains(&"345678".to_string()));
+        assert!(leads("3456789", RuleSet::default()).contains(&"34567".to_string()));
+    }
+
+    #[test]
+    fn test_rocket_kickers() {
+        let rules = RuleSet {
+            rocket_kickers: true,
+            ..Default::default()
+        };
+        assert!(!leads("34XD", RuleSet::default()).contains(&"XD34".to_string()));
+        assert!(leads("34XD", rules).contains(&"XD34".to_string()));
+        assert!(leads("3344XD", rules).contains(&"XD3344".to_string()));
+
+        // 火箭带牌按四带二比大小：管得上四个2带，管不上自己，炸弹管得上
+        let lead = Combination(Action::QuadSingle(Card::Two, Card::Three, Card::Four));
+        let moves = Hand::from("56XD").legal_moves_with(Some(&lead), &rules);
+        assert!(moves
+            .iter()
+            .any(|(c, _)| c.is_some_and(|c| c.to_string() == "XD56")));
+        assert!(moves.iter().any(|(c, _)| c.is_some_and(|c| c.category()
+            == Category::RocketSingle
+            && c.rank() == Card::BlackJoker)));
+        let lead = Combination(Action::RocketSingle(Card::Three, Card::Four));
+        let moves = Hand::from("56XD").legal_moves_with(Some(&lead), &rules);
+        assert!(!moves
+            .iter()
+            .any(|(c, _)| c.is_some_and(|c| c.category() == Category::RocketSingle)));
+        let moves = Hand::from("5555").legal_moves_with(Some(&lead), &rules);
+        assert_eq!(moves.len(), 2);
+    }
+}
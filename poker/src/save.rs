@@ -0,0 +1,572 @@
+//! 求解结果的二进制存档：文件头、规则、根局面、先序排列的方案树，最后是CRC32校验和
+
+use indextree::{Arena, NodeId};
+
+use crate::action::Action;
+use crate::card::Card;
+use crate::combination::{self, Category, Combination};
+use crate::game::{Budget, Config, Game, State};
+use crate::hand::Cards;
+use crate::rules::RuleSet;
+use crate::Error;
+
+const MAGIC: &[u8; 4] = b"DDZS";
+
+/// 存档格式的版本，编码方式或出牌规则的含义改变时加一，旧存档不再读取
+const FORMAT_VERSION: u8 = 1;
+
+/// 牌型在存档中的编号是在这里的序号
+const CATEGORIES: [Category; 18] = [
+    Category::Single,
+    Category::Sequence,
+    Category::Pair,
+    Category::PairSequence,
+    Category::Triplet,
+    Category::TripletSingle,
+    Category::TripletPair,
+    Category::TripletSequence,
+    Category::TripletSequenceSingle,
+    Category::TripletSequencePair,
+    Category::QuadSingle,
+    Category::QuadPair,
+    Category::Bomb,
+    Category::SoftBomb,
+    Category::Rocket,
+    Category::KingBomb,
+    Category::RocketSingle,
+    Category::RocketPair,
+];
+
+/// 不要、没有癞子
+const NONE: u8 = 0xFF;
+
+/// 出的牌就是牌型中的牌
+const SAME_CARDS: u8 = 0xFF;
+
+/// 节点的标记
+const PASS: u8 = 1;
+const EXPANDED: u8 = 2;
+
+/// 存档的标记
+const REFUTATION: u8 = 1;
+const EXHAUSTED: u8 = 2;
+/// 其他玩家的手牌未知，存为空手牌（robust求解的方案）
+const HIDDEN: u8 = 4;
+
+fn rank(card: Card) -> u8 {
+    (card as u16).trailing_zeros() as u8
+}
+
+fn card(rank: u8) -> Result<Card, Error> {
+    if rank > 15 {
+        return Err(Error::CorruptSave);
+    }
+    Card::from_u16(1 << rank).map_err(|_| Error::CorruptSave)
+}
+
+/// 从三到大王的所有点数
+fn ranks() -> impl Iterator<Item = Card> {
+    std::iter::successors(Some(Card::Three), |c| c.plus())
+}
+
+/// 规则和几副牌
+fn write_rules(out: &mut Vec<u8>, rules: &RuleSet, decks: u8) {
+    let flags = [
+        rules.quad_single,
+        rules.quad_pair,
+        rules.bomb_in_wings,
+        rules.kicker_same_rank,
+        rules.rocket_kickers,
+    ];
+    out.push(
+        flags
+            .iter()
+            .enumerate()
+            .fold(0, |a, (i, &f)| a | (f as u8) << i),
+    );
+    out.push(rules.min_sequence);
+    out.push(rules.laizi.map_or(NONE, rank));
+    out.push(decks);
+}
+
+/// 手牌按点数记张数，每个点数半个字节
+fn write_hand<H: Cards>(out: &mut Vec<u8>, hand: &H) {
+    let mut bytes = [0_u8; 8];
+    for (i, c) in ranks().enumerate() {
+        bytes[i / 2] |= (hand.card_count(c) as u8) << (4 * (i % 2));
+    }
+    out.extend_from_slice(&bytes);
+}
+
+/// 牌型、点数、长度（炸弹是张数）和带的牌
+fn write_action(out: &mut Vec<u8>, action: Action) {
+    if action == Action::None {
+        out.push(NONE);
+        return;
+    }
+    let (category, c, length, kickers) = Combination(action).encode();
+    out.push(CATEGORIES.iter().position(|&k| k == category).unwrap() as u8);
+    out.push(rank(c));
+    out.push(length);
+    out.push(kickers.len() as u8);
+    out.extend(kickers.into_iter().map(rank));
+}
+
+/// 按张数比较两组牌，不考虑顺序
+fn counts(cards: &[Card]) -> [u8; 16] {
+    let mut counts = [0; 16];
+    for &c in cards {
+        counts[rank(c) as usize] += 1;
+    }
+    counts
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Result<u8, Error> {
+        let b = *self.bytes.get(self.pos).ok_or(Error::CorruptSave)?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn take(&mut self, n: usize) -> Result<&[u8], Error> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or(Error::CorruptSave)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    /// 规则，几副牌与H不同时返回错误
+    fn rules<H: Cards>(&mut self) -> Result<RuleSet, Error> {
+        let flags = self.u8()?;
+        let flag = |i: u8| flags & (1 << i) != 0;
+        let min_sequence = self.u8()?;
+        let laizi = match self.u8()? {
+            NONE => None,
+            r => Some(card(r)?),
+        };
+        if self.u8()? != H::DECKS {
+            return Err(Error::CorruptSave);
+        }
+        Ok(RuleSet {
+            quad_single: flag(0),
+            quad_pair: flag(1),
+            bomb_in_wings: flag(2),
+            kicker_same_rank: flag(3),
+            rocket_kickers: flag(4),
+            min_sequence,
+            laizi,
+        })
+    }
+
+    fn hand<H: Cards>(&mut self) -> Result<H, Error> {
+        let bytes = self.take(8)?;
+        let mut hand = H::default();
+        for (i, c) in ranks().enumerate() {
+            for _ in 0..(bytes[i / 2] >> (4 * (i % 2))) & 0xF {
+                hand.draw_card(c);
+            }
+        }
+        Ok(hand.arrange())
+    }
+
+    fn action(&mut self) -> Result<Action, Error> {
+        let category = match self.u8()? {
+            NONE => return Ok(Action::None),
+            i => *CATEGORIES.get(i as usize).ok_or(Error::CorruptSave)?,
+        };
+        let c = card(self.u8()?)?;
+        let length = self.u8()?;
+        let n = self.u8()?;
+        let kickers = (0..n)
+            .map(|_| card(self.u8()?))
+            .collect::<Result<Vec<Card>, Error>>()?;
+        combination::decode(category, c, length, &kickers).ok_or(Error::CorruptSave)
+    }
+
+    /// 按先序读出方案树，用显式的栈而不是递归，损坏的存档再深也不会栈溢出。
+    /// 除了“不要”每步至少出一张牌，连续不要少于玩家数，更深的树不是合法的存档
+    fn tree<H: Cards>(
+        &mut self,
+        arena: &mut Arena<State<H>>,
+        state: State<H>,
+    ) -> Result<NodeId, Error> {
+        let max_depth = 54 * H::DECKS as usize * H::MAX_PLAYERS;
+        let root = self.node(arena, None, state)?;
+        // 从根到当前节点的路径，每层还有几个子节点没读
+        let mut stack = vec![(root, self.u16()?)];
+        while let Some((parent, remaining)) = stack.last_mut() {
+            if *remaining == 0 {
+                stack.pop();
+                continue;
+            }
+            *remaining -= 1;
+            let parent = *parent;
+            if stack.len() > max_depth {
+                return Err(Error::CorruptSave);
+            }
+            let node_id = self.node(arena, Some(parent), arena[parent].get().clone())?;
+            stack.push((node_id, self.u16()?));
+        }
+        Ok(root)
+    }
+
+    /// 读出一个节点，不含子节点数，parent为None时是根节点
+    fn node<H: Cards>(
+        &mut self,
+        arena: &mut Arena<State<H>>,
+        parent: Option<NodeId>,
+        mut state: State<H>,
+    ) -> Result<NodeId, Error> {
+        if let Some(parent) = parent {
+            let action = self.action()?;
+            let played = match self.u8()? {
+                SAME_CARDS => action.into(),
+                n => (0..n)
+                    .map(|_| card(self.u8()?))
+                    .collect::<Result<Vec<Card>, Error>>()?,
+            };
+            let mut hand = state.player.as_ref()[state.turn as usize];
+            for c in played {
+                hand.play_card(c).ok_or(Error::CorruptSave)?;
+            }
+            state = arena[parent].get().next(action, hand);
+        }
+        let flags = self.u8()?;
+        state.pass = flags & PASS != 0;
+        state.expanded = flags & EXPANDED != 0;
+        let node_id = arena.new_node(state.clone());
+        if let Some(parent) = parent {
+            parent.append(node_id, arena);
+        }
+        Ok(node_id)
+    }
+}
+
+impl<H: Cards> Game<H> {
+    /// 编码为二进制存档，只保存规则、根局面和方案树，不保存置换表和统计
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(FORMAT_VERSION);
+        write_rules(&mut out, &self.config.rules, H::DECKS);
+        let root = self.arena[self.root].get();
+        let hands = root.hands();
+        let hidden = hands.len() > 1 && hands[1..].iter().all(|h| h.is_empty());
+        out.push(
+            if self.refutation { REFUTATION } else { 0 }
+                | if self.budget.exhausted { EXHAUSTED } else { 0 }
+                | if hidden { HIDDEN } else { 0 },
+        );
+
+        out.extend([hands.len() as u8, root.landlord(), root.turn, root.passes]);
+        write_action(&mut out, root.action());
+        write_action(&mut out, root.lead);
+        for hand in hands {
+            write_hand(&mut out, hand);
+        }
+        if !self.root.is_removed(&self.arena) {
+            self.write_node(&mut out, self.root, None);
+        }
+
+        let checksum = crc32fast::hash(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    /// 出的牌、实际打出的牌（与出的牌不同时，如癞子当替身）、节点标记和子节点数
+    fn write_node(&self, out: &mut Vec<u8>, node_id: NodeId, parent: Option<&State<H>>) {
+        let state = self.arena[node_id].get();
+        if let Some(parent) = parent {
+            write_action(out, state.action());
+            let seat = parent.turn as usize;
+            let before = parent.player.as_ref()[seat];
+            let after = state.player.as_ref()[seat];
+            let played = ranks()
+                .flat_map(|c| {
+                    let n = before.card_count(c) - after.card_count(c);
+                    std::iter::repeat_n(c, n as usize)
+                })
+                .collect::<Vec<Card>>();
+            let cards: Vec<Card> = state.action().into();
+            if counts(&played) == counts(&cards) {
+                out.push(SAME_CARDS);
+            } else {
+                out.push(played.len() as u8);
+                out.extend(played.into_iter().map(rank));
+            }
+        }
+        out.push(if state.pass { PASS } else { 0 } | if state.expanded { EXPANDED } else { 0 });
+        let children = node_id
+            .children(&self.arena)
+            .filter(|c| !c.is_removed(&self.arena))
+            .collect::<Vec<NodeId>>();
+        out.extend_from_slice(&(children.len() as u16).to_le_bytes());
+        for child in children {
+            self.write_node(out, child, Some(state));
+        }
+    }
+
+    /// 读取to_bytes的存档，得到的Game可以直接用action查看方案，不能继续求解。
+    /// 文件头不对、格式版本不同、校验和不符、牌的副数与H不同或内容不合法时返回错误
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < MAGIC.len() + 1 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(Error::NotASave);
+        }
+        let version = bytes[MAGIC.len()];
+        if version != FORMAT_VERSION {
+            return Err(Error::SaveVersion(version, FORMAT_VERSION));
+        }
+        let Some(body) = bytes.len().checked_sub(4).map(|n| &bytes[..n]) else {
+            return Err(Error::CorruptSave);
+        };
+        let checksum = u32::from_le_bytes(bytes[body.len()..].try_into().unwrap());
+        if body.len() < MAGIC.len() + 1 || crc32fast::hash(body) != checksum {
+            return Err(Error::CorruptSave);
+        }
+
+        let mut reader = Reader {
+            bytes: body,
+            pos: MAGIC.len() + 1,
+        };
+        let rules = reader.rules::<H>()?;
+        let flags = reader.u8()?;
+        let players = reader.u8()?;
+        let landlord = reader.u8()?;
+        let turn = reader.u8()?;
+        let passes = reader.u8()?;
+        let action = reader.action()?;
+        let lead = reader.action()?;
+        let hands = (0..players)
+            .map(|_| reader.hand())
+            .collect::<Result<Vec<H>, Error>>()?;
+        let mut state = if flags & HIDDEN != 0 {
+            let (&ours, others) = hands.split_first().ok_or(Error::CorruptSave)?;
+            if others.iter().any(|h| !h.is_empty()) {
+                return Err(Error::CorruptSave);
+            }
+            State::public(ours, players, turn, landlord)?
+        } else {
+            State::from_hands(hands, turn, landlord)?
+        };
+        if passes >= players {
+            return Err(Error::CorruptSave);
+        }
+        state.lead = lead;
+        state.passes = passes;
+        if state.action() != action {
+            return Err(Error::CorruptSave);
+        }
+
+        let mut arena = Arena::new();
+        let root = if reader.pos < body.len() {
+            reader.tree(&mut arena, state)?
+        } else {
+            // 没有方案时根节点已删除
+            let root = arena.new_node(state);
+            root.remove(&mut arena);
+            root
+        };
+        if reader.pos != body.len() {
+            return Err(Error::CorruptSave);
+        }
+
+        let mut budget = Budget::default();
+        budget.exhausted = flags & EXHAUSTED != 0;
+        let config = Config {
+            rules,
+            ..Default::default()
+        };
+        let mut game = Game::from_parts(arena, root, config, budget);
+        game.refutation = flags & REFUTATION != 0;
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Mode, Outcome};
+    use crate::hand::{DoubleHand, Hand};
+    use crate::Deal;
+
+    /// 先序比较两棵方案树，忽略已删除的节点
+    fn assert_same_tree<H: Cards>(a: &Game<H>, a_id: NodeId, b: &Game<H>, b_id: NodeId) {
+        assert_eq!(a.arena[a_id].get(), b.arena[b_id].get());
+        let children = |g: &Game<H>, id: NodeId| {
+            id.children(&g.arena)
+                .filter(|c| !c.is_removed(&g.arena))
+                .collect::<Vec<NodeId>>()
+        };
+        let (a_children, b_children) = (children(a, a_id), children(b, b_id));
+        assert_eq!(a_children.len(), b_children.len());
+        for (&a_child, &b_child) in a_children.iter().zip(b_children.iter()) {
+            assert_same_tree(a, a_child, b, b_child);
+        }
+    }
+
+    fn round_trip<H: Cards>(game: &Game<H>) -> Game<H> {
+        let loaded = Game::from_bytes(&game.to_bytes()).unwrap();
+        assert_eq!(loaded.outcome(), game.outcome());
+        assert_eq!(loaded.is_refutation(), game.is_refutation());
+        assert_eq!(loaded.config.rules, game.config.rules);
+        if game.pass() || game.is_refutation() {
+            assert_same_tree(game, game.root, &loaded, loaded.root);
+            // 节点的编号不同，只比较出的牌
+            let cards = |g: &Game<H>| {
+                let (ours, replies) = g.action(None);
+                (ours, replies.into_iter().map(|r| r.1).collect::<Vec<_>>())
+            };
+            assert_eq!(cards(&loaded), cards(game));
+        }
+        loaded
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let state = State::new(vec!["3357899k", "34668jq"], 1).unwrap();
+        for mode in [Mode::Tree, Mode::Search] {
+            let game = Game::with_mode(state.clone(), mode);
+            round_trip(&game);
+            if let Some(refutation) = game.refutation() {
+                round_trip(&refutation);
+            }
+        }
+
+        // 所有必胜的出法、四人两副牌
+        let game = Game::with_config(
+            State::new(vec!["3344", "5"], 0).unwrap(),
+            Config {
+                all_wins: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(round_trip(&game).winning_moves(None).len(), 2);
+        let state =
+            State::<DoubleHand>::from_hands(vec!["333334", "2222", "5", "6"], 0, 0).unwrap();
+        let game = Game::from_state(state);
+        round_trip(&game);
+        assert!(matches!(
+            Game::<Hand>::from_bytes(&game.to_bytes()),
+            Err(Error::CorruptSave)
+        ));
+
+        // 癞子当替身时，实际打出的牌与牌型中的牌不同
+        let config = Config {
+            rules: RuleSet {
+                laizi: Some(Card::Seven),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let game = Game::with_config(State::new(vec!["37", "a"], 0).unwrap(), config);
+        let loaded = round_trip(&game);
+        assert_eq!(loaded.action(None).0, vec![Card::Three, Card::Three]);
+
+        // 必败时没有方案，对方的反驳方案也能保存
+        let game = Game::new(vec!["3", "4", "5"], 1).unwrap();
+        assert_eq!(round_trip(&game).outcome(), Outcome::Loss);
+        let game = Game::with_mode(State::new(vec!["K3", "A"], 0).unwrap(), Mode::Search);
+        let refutation = game.refutation().unwrap();
+        assert!(refutation.pass());
+        assert!(round_trip(&refutation).is_refutation());
+    }
+
+    #[test]
+    fn test_robust_round_trip() {
+        // 其他玩家的手牌未知，存为空手牌
+        let deal = Deal {
+            hand: "KK3".into(),
+            unseen: "A4".into(),
+            counts: vec![1],
+            ..Default::default()
+        };
+        let game = Game::robust(&deal, Config::default()).unwrap();
+        assert!(game.pass());
+        let loaded = round_trip(&game);
+        assert!(loaded.arena[loaded.root].get().hands()[1].is_empty());
+
+        // 只有robust的存档才能有空手牌
+        let mut bytes = game.to_bytes();
+        let flags = MAGIC.len() + 1 + 4;
+        bytes[flags] &= !HIDDEN;
+        let body = bytes.len() - 4;
+        let checksum = crc32fast::hash(&bytes[..body]);
+        bytes[body..].copy_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(
+            Game::<Hand>::from_bytes(&bytes),
+            Err(Error::EmptyHand(1))
+        ));
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        // 一直“不要”的很深的方案树，校验和正确也要返回错误，不能栈溢出
+        let game = Game::new(vec!["3", "4"], 0).unwrap();
+        let mut tree = Vec::new();
+        game.write_node(&mut tree, game.root, None);
+        let bytes = game.to_bytes();
+        let mut body = bytes[..bytes.len() - 4 - tree.len()].to_vec();
+        body.extend([0, 1, 0]);
+        for _ in 0..1_000_000 {
+            body.extend([NONE, SAME_CARDS, 0, 1, 0]);
+        }
+        body.extend([NONE, SAME_CARDS, 0, 0, 0]);
+        let checksum = crc32fast::hash(&body);
+        body.extend_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(
+            Game::<Hand>::from_bytes(&body),
+            Err(Error::CorruptSave)
+        ));
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let bytes = Game::new(vec!["3344", "5"], 0).unwrap().to_bytes();
+        assert!(bytes.len() < 64, "{}", bytes.len());
+
+        let mut corrupt = bytes.clone();
+        corrupt[bytes.len() / 2] ^= 1;
+        assert!(matches!(
+            Game::<Hand>::from_bytes(&corrupt),
+            Err(Error::CorruptSave)
+        ));
+        assert!(matches!(
+            Game::<Hand>::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(Error::CorruptSave)
+        ));
+        assert!(matches!(
+            Game::<Hand>::from_bytes(b"DDZ"),
+            Err(Error::NotASave)
+        ));
+        assert!(matches!(
+            Game::<Hand>::from_bytes(b"PNG\x01\x00"),
+            Err(Error::NotASave)
+        ));
+
+        let mut outdated = bytes.clone();
+        outdated[MAGIC.len()] = FORMAT_VERSION + 1;
+        assert!(matches!(
+            Game::<Hand>::from_bytes(&outdated),
+            Err(Error::SaveVersion(v, FORMAT_VERSION)) if v == FORMAT_VERSION + 1
+        ));
+
+        // 校验和正确但内容不合法
+        let mut invalid = bytes[..bytes.len() - 4].to_vec();
+        invalid.push(0);
+        invalid.extend_from_slice(&crc32fast::hash(&invalid).to_le_bytes());
+        assert!(matches!(
+            Game::<Hand>::from_bytes(&invalid),
+            Err(Error::CorruptSave)
+        ));
+    }
+}
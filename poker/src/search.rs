@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+
+use indextree::{Arena, NodeId};
+
+use crate::action::Action;
+use crate::game::{Budget, Config, Mode, Position, State, ALL_WINS_NODES};
+use crate::hand::{Cards, Hand};
+use crate::rules::RuleSet;
+
+/// 没有设置table_bytes时置换表最多记录的局面数，超出后清空重来，避免耗尽内存
+const TABLE_CAPACITY: usize = 1 << 20;
+
+/// 不建树的深度优先与或搜索：我方回合有一种出法必胜即可，对方回合所有出法都要必胜
+#[derive(Default)]
+pub(crate) struct Search<H: Cards = Hand> {
+    /// 我方是否选择最少回合出完的出法
+    shortest: bool,
+    /// 我方回合是否保留所有必胜的出法
+    all_wins: bool,
+    /// 置换表：局面 -> 我方是否必胜
+    table: HashMap<Position<H>, bool>,
+    /// 置换表：局面 -> 我方必胜时最少还需要几个我方回合，必败为None
+    distance: HashMap<Position<H>, Option<u8>>,
+    /// 置换表最多记录的局面数
+    capacity: usize,
+    pub(crate) budget: Budget,
+    /// 当前搜索深度
+    depth: usize,
+    rules: RuleSet,
+}
+
+impl<H: Cards> Search<H> {
+    pub(crate) fn new(config: &Config, budget: Budget) -> Self {
+        Search {
+            shortest: config.mode == Mode::Shortest,
+            all_wins: config.all_wins,
+            capacity: config.table_bytes.map_or(TABLE_CAPACITY, |bytes| {
+                (bytes / entry_size::<Position<H>, Option<u8>>()).max(1)
+            }),
+            budget,
+            rules: config.rules,
+            ..Default::default()
+        }
+    }
+
+    /// 求解并只生成必胜方案的子树，返回根节点
+    pub(crate) fn strategy(&mut self, arena: &mut Arena<State<H>>, mut state: State<H>) -> NodeId {
+        state.pass = if self.shortest {
+            self.distance(&state).is_some()
+        } else {
+            self.solve(&state)
+        };
+        let pass = state.pass;
+        let root = arena.new_node(state);
+        if pass && !self.budget.exhausted {
+            self.build(arena, root);
+        }
+        self.finish(arena, root);
+        root
+    }
+
+    /// 求解并在我方必败时生成对方的反驳方案，返回根节点
+    pub(crate) fn refutation(
+        &mut self,
+        arena: &mut Arena<State<H>>,
+        mut state: State<H>,
+    ) -> NodeId {
+        state.pass = !self.solve(&state) && !self.budget.exhausted;
+        let pass = state.pass;
+        let root = arena.new_node(state);
+        if pass {
+            self.refute(arena, root);
+        }
+        self.finish(arena, root);
+        root
+    }
+
+    fn finish(&mut self, arena: &mut Arena<State<H>>, root: NodeId) {
+        // 生成方案时预算用完，方案不完整，按胜负未知处理
+        if self.budget.exhausted {
+            for child in root.children(arena).collect::<Vec<NodeId>>() {
+                child.remove_subtree(arena);
+            }
+            arena.get_mut(root).unwrap().get_mut().pass = false;
+        }
+        self.table = HashMap::new();
+        self.distance = HashMap::new();
+    }
+
+    /// 当前局面我方是否必胜
+    fn solve(&mut self, state: &State<H>) -> bool {
+        let position = state.position();
+        if let Some(&won) = self.table.get(&position) {
+            self.budget.stats.transpositions += 1;
+            return won;
+        }
+        if !self.budget.spend() {
+            return false;
+        }
+        self.depth += 1;
+        self.budget.observe(self.depth, self.table.len());
+
+        let ours = state.our_turn();
+        let follow = state.player.as_ref()[state.turn as usize].follow(&state.lead, &self.rules);
+        let total = follow.len();
+        // 能一手出完的，不用再往下搜
+        let won = if follow.iter().any(|(_, hand)| hand.is_empty()) {
+            self.budget.stats.pruned += total - 1;
+            ours
+        } else {
+            let mut won = !ours;
+            for (i, (action, hand)) in follow.into_iter().enumerate() {
+                if self.solve(&state.next(action, hand)) == ours {
+                    self.budget.stats.pruned += total - i - 1;
+                    won = ours;
+                    break;
+                }
+            }
+            won
+        };
+        self.depth -= 1;
+
+        // 预算用完时的结果不可靠，不记入置换表
+        if self.budget.exhausted {
+            return won;
+        }
+        if self.table.len() >= self.capacity {
+            log::debug!("置换表已满，清空");
+            self.table.clear();
+        }
+        self.table.insert(position, won);
+        won
+    }
+
+    /// 极小化极大：我方选回合最少的出法，对方选让我方回合最多的出法
+    fn distance(&mut self, state: &State<H>) -> Option<u8> {
+        let position = state.position();
+        if let Some(&distance) = self.distance.get(&position) {
+            self.budget.stats.transpositions += 1;
+            return distance;
+        }
+        if !self.budget.spend() {
+            return None;
+        }
+        self.depth += 1;
+        self.budget.observe(self.depth, self.distance.len());
+
+        let ours = state.our_turn();
+        let follow = state.player.as_ref()[state.turn as usize].follow(&state.lead, &self.rules);
+        let total = follow.len();
+        let distance = if follow.iter().any(|(_, hand)| hand.is_empty()) {
+            self.budget.stats.pruned += total - 1;
+            ours.then_some(1)
+        } else if ours {
+            let mut best: Option<u8> = None;
+            for (action, hand) in follow {
+                if let Some(d) = self.distance(&state.next(action, hand)) {
+                    best = Some(best.map_or(d, |b| b.min(d)));
+                }
+            }
+            best.map(|d| d + 1)
+        } else {
+            let mut worst = Some(0);
+            for (i, (action, hand)) in follow.into_iter().enumerate() {
+                let Some(d) = self.distance(&state.next(action, hand)) else {
+                    self.budget.stats.pruned += total - i - 1;
+                    worst = None;
+                    break;
+                };
+                worst = worst.map(|w: u8| w.max(d));
+            }
+            worst
+        };
+        self.depth -= 1;
+
+        if self.budget.exhausted {
+            return distance;
+        }
+        if self.distance.len() >= self.capacity {
+            log::debug!("置换表已满，清空");
+            self.distance.clear();
+        }
+        self.distance.insert(position, distance);
+        distance
+    }
+
+    /// 我方打出action后剩余hand，最少还需要几个我方回合，出完为0
+    fn remaining(&mut self, state: &State<H>, action: Action, hand: H) -> Option<u8> {
+        if hand.is_empty() {
+            Some(0)
+        } else {
+            self.distance(&state.next(action, hand))
+        }
+    }
+
+    /// 在已证明必胜的节点下，展开我方的一种必胜出法，或对方的所有出法
+    fn build(&mut self, arena: &mut Arena<State<H>>, node_id: NodeId) {
+        let node = arena.get_mut(node_id).unwrap().get_mut();
+        let state = node.clone();
+        node.expanded = true;
+        let mut follow =
+            state.player.as_ref()[state.turn as usize].follow(&state.lead, &self.rules);
+
+        // 我方只保留一种必胜出法，或者保留所有必胜出法
+        let ours = state.our_turn();
+        if ours {
+            let limit = if self.all_wins { usize::MAX } else { 1 };
+            follow = if self.shortest {
+                // 回合少的出法排在前面
+                let mut wins = follow
+                    .into_iter()
+                    .filter_map(|(action, hand)| {
+                        let d = self.remaining(&state, action, hand)?;
+                        Some((d, action, hand))
+                    })
+                    .collect::<Vec<_>>();
+                wins.sort_by_key(|(d, ..)| *d);
+                wins.into_iter()
+                    .take(limit)
+                    .map(|(_, action, hand)| (action, hand))
+                    .collect()
+            } else {
+                follow
+                    .into_iter()
+                    .filter(|&(action, hand)| {
+                        hand.is_empty() || self.solve(&state.next(action, hand))
+                    })
+                    .take(limit)
+                    .collect()
+            };
+        }
+
+        for (i, (action, hand)) in follow.into_iter().enumerate() {
+            // 方案树的节点太多时，其余必胜出法不再保留
+            if ours && i > 0 && self.budget.stats.created >= ALL_WINS_NODES {
+                break;
+            }
+            // 预算用完时方案不完整，由finish删除
+            if !self.budget.grow() {
+                return;
+            }
+            let mut next = state.next(action, hand);
+            next.pass = true;
+            let child = arena.new_node(next);
+            node_id.append(child, arena);
+            if !hand.is_empty() {
+                self.build(arena, child);
+            }
+        }
+    }
+
+    /// 在已证明必败的节点下，展开我方的所有出法，或对方的一种反驳出法
+    fn refute(&mut self, arena: &mut Arena<State<H>>, node_id: NodeId) {
+        let node = arena.get_mut(node_id).unwrap().get_mut();
+        node.expanded = true;
+        let state = node.clone();
+        let mut follow =
+            state.player.as_ref()[state.turn as usize].follow(&state.lead, &self.rules);
+
+        if !state.our_turn() {
+            follow = follow
+                .into_iter()
+                .filter(|&(action, hand)| hand.is_empty() || !self.solve(&state.next(action, hand)))
+                .take(1)
+                .collect();
+        }
+
+        for (action, hand) in follow {
+            if !self.budget.grow() {
+                return;
+            }
+            let mut next = state.next(action, hand);
+            next.pass = true;
+            let child = arena.new_node(next);
+            node_id.append(child, arena);
+            if !hand.is_empty() {
+                self.refute(arena, child);
+            }
+        }
+    }
+}
+
+/// 置换表中一条记录大约占用的字节数：键值对本身，加上HashMap每个槽位一个字节的控制信息
+pub(crate) fn entry_size<K, V>() -> usize {
+    std::mem::size_of::<(K, V)>() + 1
+}
@@ -0,0 +1,237 @@
+//! serde编码：牌、手牌和牌型都编码为与Display相同的字符串，局面编码为结构体
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::action::Action;
+use crate::card::{Card, SuitCard};
+use crate::combination::{self, Category, Combination};
+use crate::game::State;
+use crate::hand::{Cards, DoubleHand, Hand};
+
+fn serialize_str<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+fn deserialize_str<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(de::Error::custom)
+}
+
+/// 用Display和FromStr编码为字符串
+macro_rules! string_serde {
+    ($($t:ty),*) => {$(
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serialize_str(self, serializer)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserialize_str(deserializer)
+            }
+        }
+    )*};
+}
+
+// 牌型按出的牌编码，解码时与FromStr一样按一副牌识别，软炸弹解码为炸弹；局面中的牌型另按Play编码。
+// 手牌编码为不带花色的规范写法，如"3345XD"，两种写法都能解码
+string_serde!(Card, SuitCard, Combination, Hand, DoubleHand);
+
+/// 局面的编码，不含求解过程中的标记
+#[derive(Serialize, Deserialize)]
+#[serde(bound(deserialize = "H: Cards + Deserialize<'de>"))]
+struct Position<H> {
+    /// 各玩家的手牌
+    hands: Vec<H>,
+    /// 上一手出的牌，不要时为空
+    action: Option<Play>,
+    /// 当前回合需要应对的牌，自由出牌时为空
+    lead: Option<Play>,
+    /// 连续“不要”的次数
+    passes: u8,
+    landlord: u8,
+    turn: u8,
+}
+
+/// 局面中的牌型按各部分编码，软炸弹和带牌的理解都能原样还原。炸弹的长度是张数
+#[derive(Serialize, Deserialize, PartialEq)]
+struct Play {
+    category: Category,
+    rank: Card,
+    length: u8,
+    kickers: Vec<Card>,
+}
+
+impl Play {
+    fn new(action: Action) -> Option<Self> {
+        let (category, rank, length, kickers) =
+            (action != Action::None).then(|| Combination(action).encode())?;
+        Some(Play {
+            category,
+            rank,
+            length,
+            kickers,
+        })
+    }
+
+    /// 还原的牌型要能按手牌的副数识别，否则不是合法的牌
+    fn action<H: Cards, E: de::Error>(&self) -> Result<Action, E> {
+        let action = combination::decode(self.category, self.rank, self.length, &self.kickers)
+            .ok_or_else(|| E::custom(format!("没有这样的牌型：{:?}", self.category)))?;
+        Combination::classify::<H>(&Combination(action).cards()).map_err(E::custom)?;
+        Ok(action)
+    }
+}
+
+impl<H: Cards + Serialize> Serialize for State<H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Position {
+            hands: self.hands().to_vec(),
+            action: Play::new(self.action()),
+            lead: Play::new(self.lead),
+            passes: self.passes,
+            landlord: self.landlord(),
+            turn: self.turn,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, H: Cards + Deserialize<'de>> Deserialize<'de> for State<H> {
+    /// 与State::from_hands一样检查手牌和座位
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let p = Position::<H>::deserialize(deserializer)?;
+        let players = p.hands.len();
+        if p.passes as usize >= players.max(1) {
+            return Err(de::Error::custom(format!(
+                "连续不要的次数{}超出玩家人数{}",
+                p.passes, players
+            )));
+        }
+        let mut state =
+            State::from_hands(p.hands, p.turn, p.landlord).map_err(de::Error::custom)?;
+        state.lead = match &p.lead {
+            Some(play) => play.action::<H, D::Error>()?,
+            None => Action::None,
+        };
+        state.passes = p.passes;
+        // 上一手出的牌由需要应对的牌和不要的次数决定
+        if Play::new(state.action()) != p.action {
+            return Err(de::Error::custom("上一手出的牌与需要应对的牌不一致"));
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(serde_json::to_string(&Card::Ten).unwrap(), "\"T\"");
+        assert_eq!(serde_json::from_str::<Card>("\"t\"").unwrap(), Card::Ten);
+        let suit_card = SuitCard::new(Card::Ace, 3);
+        let json = serde_json::to_string(&suit_card).unwrap();
+        assert_eq!(json, "\"♠A\"");
+        assert_eq!(serde_json::from_str::<SuitCard>(&json).unwrap(), suit_card);
+
+        let hand = Hand::from("D53X4T3").arrange();
+        let json = serde_json::to_string(&hand).unwrap();
+        assert_eq!(json, "\"3345TXD\"");
+        assert_eq!(serde_json::from_str::<Hand>(&json).unwrap(), hand);
+        let hand = "333333XXDD".parse::<DoubleHand>().unwrap();
+        let json = serde_json::to_string(&hand).unwrap();
+        assert_eq!(serde_json::from_str::<DoubleHand>(&json).unwrap(), hand);
+        assert!(serde_json::from_str::<Hand>(&json).is_err());
+        assert!(serde_json::from_str::<Hand>("\"33y\"").is_err());
+
+        let combination = "333444".parse::<Combination>().unwrap();
+        let json = serde_json::to_string(&combination).unwrap();
+        assert_eq!(json, "\"333444\"");
+        assert_eq!(
+            serde_json::from_str::<Combination>(&json).unwrap(),
+            combination
+        );
+        assert!(serde_json::from_str::<Combination>("\"34\"").is_err());
+    }
+
+    #[test]
+    fn test_state() {
+        let state = State::with_landlord(vec!["3345", "66", "7"], 1, 2).unwrap();
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(
+            json,
+            r#"{"hands":["3345","66","7"],"action":null,"lead":null,"passes":0,"landlord":2,"turn":1}"#
+        );
+        assert_eq!(serde_json::from_str::<State>(&json).unwrap(), state);
+
+        // 出过牌之后的局面
+        let hand = Hand::from("345").arrange();
+        let next = state.next(Action::Single(Card::Three), hand);
+        let json = serde_json::to_string(&next).unwrap();
+        let play = r#"{"category":"Single","rank":"3","length":1,"kickers":[]}"#;
+        assert!(
+            json.contains(&format!(r#""action":{play},"lead":{play}"#)),
+            "{}",
+            json
+        );
+        assert_eq!(serde_json::from_str::<State>(&json).unwrap(), next);
+
+        // 两副牌的局面按两副牌识别牌型
+        let state = State::<DoubleHand>::from_hands(vec!["333334", "44444"], 0, 0).unwrap();
+        let bomb = Combination::classify_str::<DoubleHand>("33333").unwrap();
+        let next = state.next(bomb.0, DoubleHand::from("4").arrange());
+        let json = serde_json::to_string(&next).unwrap();
+        assert!(
+            json.contains(r#""lead":{"category":"Bomb","rank":"3","length":5"#),
+            "{}",
+            json
+        );
+        assert_eq!(
+            serde_json::from_str::<State<DoubleHand>>(&json).unwrap(),
+            next
+        );
+        assert!(serde_json::from_str::<State>(&json).is_err());
+
+        // 软炸弹、火箭带牌和带的牌有多种理解的牌型都原样还原
+        let state = State::from_hands(vec!["333444555666XD", "7777"], 0, 0).unwrap();
+        for action in [
+            Action::SoftBomb(Card::Three),
+            Action::RocketSingle(Card::Three, Card::Four),
+            Action::RocketPair(Card::Three, Card::Four),
+            Action::TripletSequence3Single(Card::Four, Card::Three, Card::Three, Card::Three),
+        ] {
+            let next = state.next(action, Hand::from("5").arrange());
+            let json = serde_json::to_string(&next).unwrap();
+            assert_eq!(
+                serde_json::from_str::<State>(&json).unwrap(),
+                next,
+                "{}",
+                json
+            );
+        }
+
+        for json in [
+            r#"{"hands":[],"action":null,"lead":null,"passes":0,"landlord":0,"turn":0}"#,
+            r#"{"hands":["3","3"],"action":null,"lead":null,"passes":0,"landlord":0,"turn":2}"#,
+            r#"{"hands":["3","4"],"action":null,"lead":null,"passes":2,"landlord":0,"turn":0}"#,
+            r#"{"hands":["3","4"],"action":{"category":"Single","rank":"5","length":1,"kickers":[]},"lead":null,"passes":0,"landlord":0,"turn":0}"#,
+            // 越过A的顺子、一副牌中的五张炸弹
+            r#"{"hands":["3","4"],"action":{"category":"Sequence","rank":"A","length":11,"kickers":[]},"lead":{"category":"Sequence","rank":"A","length":11,"kickers":[]},"passes":0,"landlord":0,"turn":0}"#,
+            r#"{"hands":["3","4"],"action":{"category":"Bomb","rank":"3","length":5,"kickers":[]},"lead":{"category":"Bomb","rank":"3","length":5,"kickers":[]},"passes":0,"landlord":0,"turn":0}"#,
+        ] {
+            assert!(serde_json::from_str::<State>(json).is_err(), "{}", json);
+        }
+    }
+}
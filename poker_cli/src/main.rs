@@ -1,7 +1,11 @@
 use std::io::Write;
+use std::time::Duration;
 
 use anyhow::Result;
-use poker::Game;
+use poker::{Combination, Config, Game, Hand, Outcome, State};
+
+/// 单局求解的时限
+const SOLVE_TIMEOUT: Duration = Duration::from_secs(60);
 
 fn main() {
     if std::env::var("RUST_LOG").is_err() {
@@ -18,29 +22,43 @@ fn main() {
 
 fn interactive() -> Result<()> {
     loop {
-        let hand_own = read("请输入自己的手牌\n");
-        let hand_opponent = read("请输入对手的手牌\n");
+        let hand_own = read_hand("请输入自己的手牌\n");
+        let hand_opponent = read_hand("请输入对手的手牌\n");
         let turn = read("请选择先手： 0-自己先手  1-对方先手； 默认0-自己先手\n")
             .parse::<u8>()
             .map(|t| t != 0)
             .unwrap_or_default();
 
-        let game = match poker::Game::new(
-            vec![hand_own.as_str(), hand_opponent.as_str()],
-            turn.then(|| 1).unwrap_or_default(),
-        ) {
-            Ok(game) => game,
+        let state = match State::new(vec![hand_own, hand_opponent], if turn { 1 } else { 0 }) {
+            Ok(state) => state,
             Err(e) => {
                 log::error!("创建游戏失败： {}", e);
                 continue;
             }
         };
+        let config = Config {
+            timeout: Some(SOLVE_TIMEOUT),
+            parallel: true,
+            ..Default::default()
+        };
+        let game = Game::with_config(state, config);
+        log::info!("{:?}", game.stats());
 
-        if game.pass() {
-            std::io::stdout().write_all("有必胜的方案\n".as_ref())?;
-            play(game, turn);
-        } else {
-            std::io::stdout().write_all("没有必胜的方案\n".as_ref())?;
+        match game.outcome() {
+            Outcome::Win => {
+                std::io::stdout().write_all("有必胜的方案\n".as_ref())?;
+                play(game, turn);
+            }
+            Outcome::Loss => {
+                std::io::stdout().write_all("没有必胜的方案\n".as_ref())?;
+                // 逐步演示对方如何应对我方的每一种出法
+                if let Some(refutation) = game.refutation() {
+                    play(refutation, turn);
+                }
+            }
+            Outcome::Unknown => {
+                std::io::stdout().write_all("计算超时，无法判断是否有必胜的方案\n".as_ref())?;
+            }
         };
     }
 }
@@ -60,6 +78,20 @@ fn read(hint: &str) -> String {
     }
 }
 
+/// 读入手牌，有输错的牌时提示原因并重新输入
+fn read_hand(hint: &str) -> Hand {
+    loop {
+        match read(hint).parse::<Hand>() {
+            Ok(hand) => return hand,
+            Err(e) => {
+                std::io::stdout()
+                    .write_all(format!("请重新输入! {}\n", e).as_bytes())
+                    .ok();
+            }
+        }
+    }
+}
+
 fn read_inner(hint: &str) -> Result<String> {
     std::io::stdout().write_all(hint.as_ref())?;
     let mut buffer = String::new();
@@ -69,16 +101,22 @@ fn read_inner(hint: &str) -> Result<String> {
 }
 
 fn play(game: Game, init_turn: bool) {
+    // 必胜方案由程序替我方出牌，反驳方案由程序替对方出牌
+    let (auto, manual, end) = if game.is_refutation() {
+        ("对方", "我方", "失败！\n\n")
+    } else {
+        ("我方", "对方", "胜利！\n\n")
+    };
     let mut node_id = game.root;
     let mut turn = init_turn;
     loop {
-        if !turn {
+        if turn == game.is_refutation() {
             if let Some(n) = node_id.children(&game.arena).next() {
                 node_id = n;
             }
             let state = game.arena.get(node_id).unwrap().get();
             std::io::stdout()
-                .write_all(format!("我方出牌：{}\n", state.action_string()).as_ref())
+                .write_all(format!("{}出牌：{}\n", auto, state.action_string()).as_ref())
                 .ok();
         } else {
             let actions = node_id
@@ -87,13 +125,13 @@ fn play(game: Game, init_turn: bool) {
                 .collect::<Vec<_>>();
 
             if actions.is_empty() {
-                std::io::stdout().write_all("胜利！\n\n".as_ref()).ok();
+                std::io::stdout().write_all(end.as_ref()).ok();
                 return;
             }
 
-            let mut action = read(&*format!(
-                "{:?}\n请输入对方的出牌：(retract-悔一步 retry-重来 new-下一局 quit-退出)\n",
-                actions
+            let mut action = read(&format!(
+                "{:?}\n请输入{}的出牌：(retract-悔一步 retry-重来 new-下一局 quit-退出)\n",
+                actions, manual
             ))
             .to_uppercase();
 
@@ -120,7 +158,10 @@ fn play(game: Game, init_turn: bool) {
 
             let Some(n) = node_id.children(&game.arena).find(|child| game.arena.get(*child).unwrap().get().action_string().eq(&action)) else
             {
-                std::io::stdout().write_all("无效的出牌！\n".as_ref()).ok();
+                let reason = invalid_reason(game.arena[node_id].get(), &action);
+                std::io::stdout()
+                    .write_all(format!("无效的出牌！{}\n", reason).as_ref())
+                    .ok();
                 continue;
             };
             node_id = n;
@@ -128,3 +169,15 @@ fn play(game: Game, init_turn: bool) {
         turn = !turn;
     }
 }
+
+/// 输入的牌不在方案中的原因
+fn invalid_reason(state: &State, action: &str) -> String {
+    let played = match action.parse::<Combination>() {
+        Ok(played) => played,
+        Err(e) => return e.to_string(),
+    };
+    match state.lead().map(|lead| (played.beats(&lead), lead)) {
+        Some((Err(e), lead)) => format!("管不上{}: {}", lead, e),
+        _ => "手里没有这些牌".to_string(),
+    }
+}